@@ -1,17 +1,33 @@
 class Bootstrapper extends EventTarget {
     _currentTask = undefined;
+    _downloadProgress = undefined;
+    _versionCheckFailure = undefined;
 
     constructor(startingTask) {
         super();
 
         this._currentTask = startingTask;
         setInterval(() => this._pollForTaskChanges(), 100);
+        setInterval(() => this._pollForDownloadProgress(), 100);
+        setInterval(() => this._pollForVersionCheckFailure(), 100);
     }
 
     getCurrentTask() {
         return this._currentTask;
     }
 
+    /// The latest progress of the client download, or undefined if it hasn't started.
+    /// `fraction` and `secondsRemaining` are undefined until they can be worked out.
+    getDownloadProgress() {
+        return this._downloadProgress;
+    }
+
+    /// Why checking for client updates failed ("NoNetwork" or "ServerError"), or undefined if it hasn't failed.
+    /// The installed client is launched instead, if it can be.
+    getVersionCheckFailure() {
+        return this._versionCheckFailure;
+    }
+
     /// Polls the Bootstrapper for task changes via the custom protocol.
     /// TODO: Use WebSockets for communication.
     _pollForTaskChanges() {
@@ -21,10 +37,64 @@ class Bootstrapper extends EventTarget {
             .then((task) => {
                 if (task && task !== this._currentTask) {
                     console.debug("Task changed to: " + task);
-    
+
                     this._currentTask = task;
                     this.dispatchEvent(new Event("NewTask"))
                 }
             });
     }
+
+    /// Polls the Bootstrapper for download progress via the custom protocol, while the client is downloading.
+    _pollForDownloadProgress() {
+        if (this._currentTask !== "DownloadingClient") {
+            return;
+        }
+
+        fetch("bootstrapper://server/download_progress")
+            .then((response) => response.headers)
+            .then((headers) => {
+                if (!headers.has("x-bytes-received")) {
+                    return;
+                }
+
+                const bytesReceived = Number(headers.get("x-bytes-received"));
+                const totalBytes = Number(headers.get("x-total-bytes"));
+                const bytesPerSecond = Number(headers.get("x-bytes-per-second"));
+
+                const previous = this._downloadProgress;
+                if (previous && previous.bytesReceived === bytesReceived && previous.totalBytes === totalBytes) {
+                    return;
+                }
+
+                this._downloadProgress = {
+                    file: headers.get("x-download-file"),
+                    bytesReceived,
+                    totalBytes,
+                    bytesPerSecond,
+                    fraction: totalBytes > 0 ? Math.min(bytesReceived / totalBytes, 1) : undefined,
+                    secondsRemaining: bytesPerSecond > 0
+                        ? Math.max(totalBytes - bytesReceived, 0) / bytesPerSecond
+                        : undefined,
+                };
+                this.dispatchEvent(new CustomEvent("DownloadProgress", { detail: this._downloadProgress }));
+            });
+    }
+
+    /// Polls the Bootstrapper for a failed update check via the custom protocol, until there is one.
+    _pollForVersionCheckFailure() {
+        if (this._versionCheckFailure) {
+            return;
+        }
+
+        fetch("bootstrapper://server/version_check_failure")
+            .then((response) => response.headers.get("x-version-check-failure"))
+            .then((failure) => {
+                if (failure && !this._versionCheckFailure) {
+                    console.debug("Version check failed: " + failure);
+
+                    this._versionCheckFailure = failure;
+                    this.dispatchEvent(new CustomEvent("VersionCheckFailed", { detail: failure }));
+                }
+            });
+    }
 }
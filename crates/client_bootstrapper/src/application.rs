@@ -16,14 +16,20 @@ use wry::{
         event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget},
         keyboard::{KeyCode, ModifiersState},
         menu::{MenuBar, MenuItemAttributes},
-        platform::macos::WindowBuilderExtMacOS,
         window::{WindowBuilder, WindowId},
     },
     http::Response,
     webview::{WebContext, WebView, WebViewBuilder},
 };
 
-use crate::{async_runtime::Message, manifest::ProjectManifest};
+#[cfg(target_os = "macos")]
+use wry::application::platform::macos::WindowBuilderExtMacOS;
+
+use crate::{
+    async_runtime::Message,
+    downloader::{DownloadProgress, VersionCheckFailure},
+    manifest::ProjectManifest,
+};
 
 const AUTH_WEBVIEW_INIT_SCRIPT: &str = include_str!("../resources/js/auth_webview_hook.js");
 const BOOTSTRAPPER_SDK: &str = include_str!("../resources/js/bootstrapper_sdk.js");
@@ -60,9 +66,12 @@ impl<'a> Application<'a> {
         let proxy = event_loop.create_proxy();
 
         let game_name = self.manifest.game.name.to_owned();
+        let sign_up_url = self.manifest.endpoints().www;
         let assets_path = self.root_path.join("assets");
 
         let current_task = Arc::new(Mutex::new(Message::CheckingForUpdates));
+        let download_progress = Arc::new(Mutex::new(None::<DownloadProgress>));
+        let version_check_failure = Arc::new(Mutex::new(None::<VersionCheckFailure>));
         let authentication_webview = Arc::new(Mutex::new(None));
 
         let mut menu = MenuBar::new();
@@ -77,23 +86,30 @@ impl<'a> Application<'a> {
         let window_width = self.manifest.design.width;
         let window_height = self.manifest.design.height;
 
-        let window = WindowBuilder::new()
+        let window_builder = WindowBuilder::new()
             .with_always_on_top(true)
             .with_decorations(false)
-            .with_has_shadow(true)
-            .with_movable_by_window_background(true)
             .with_inner_size(LogicalSize::new(window_width, window_height))
             .with_max_inner_size(LogicalSize::new(window_width, window_height))
             .with_menu(menu)
             // There are actually three layer of background color when creating WebView window.
             // The first is window background...
-            .with_transparent(true)
+            .with_transparent(true);
+
+        #[cfg(target_os = "macos")]
+        let window_builder = window_builder
+            .with_has_shadow(true)
+            .with_movable_by_window_background(true);
+
+        let window = window_builder
             .build(&event_loop)
             .context("Failed to build window")?;
 
         let mut web_context = WebContext::new(None);
 
         let current_task2 = Arc::clone(&current_task);
+        let download_progress2 = Arc::clone(&download_progress);
+        let version_check_failure2 = Arc::clone(&version_check_failure);
         let webview = WebViewBuilder::new(window)?
             .with_web_context(&mut web_context)
             // The second is on webview...
@@ -117,6 +133,35 @@ impl<'a> Application<'a> {
                         .map_err(Into::into);
                 }
 
+                if name == "download_progress" {
+                    let download_progress = download_progress2.lock().unwrap();
+                    let mut response = Response::builder().header(CONTENT_TYPE, "text/plain");
+
+                    // No headers until the download has started.
+                    if let Some(progress) = &*download_progress {
+                        response = response
+                            .header("x-download-file", progress.file.as_str())
+                            .header("x-bytes-received", progress.bytes_received)
+                            .header("x-total-bytes", progress.total_bytes)
+                            .header("x-bytes-per-second", progress.bytes_per_second);
+                    }
+
+                    return response.body(vec![]).map_err(Into::into);
+                }
+
+                if name == "version_check_failure" {
+                    let version_check_failure = version_check_failure2.lock().unwrap();
+                    let mut response = Response::builder().header(CONTENT_TYPE, "text/plain");
+
+                    // No header unless the installed client is being used because the version check failed.
+                    if let Some(failure) = &*version_check_failure {
+                        response = response
+                            .header("x-version-check-failure", failure.to_string().as_str());
+                    }
+
+                    return response.body(vec![]).map_err(Into::into);
+                }
+
                 if name == "bootstrapper_sdk.js" {
                     return Response::builder()
                         .header(CONTENT_TYPE, "text/javascript")
@@ -152,11 +197,16 @@ impl<'a> Application<'a> {
             if let Ok(message) = async_thread_receive.try_recv() {
                 log::debug!("Got message from async thread: {message:?}");
 
-                if message == Message::PromptForAuth {
+                if let Message::DownloadProgress(progress) = message {
+                    *download_progress.lock().unwrap() = Some(progress);
+                } else if let Message::VersionCheckFailed(failure) = message {
+                    *version_check_failure.lock().unwrap() = Some(failure);
+                } else if message == Message::PromptForAuth {
                     // TODO: Proper error handling
                     let (window_id, webview) = Self::create_authentication_window(
                         event_loop,
                         &mut web_context,
+                        &sign_up_url,
                         application_thread_sender.clone(),
                         proxy.clone(),
                     )
@@ -216,6 +266,7 @@ impl<'a> Application<'a> {
     fn create_authentication_window(
         event_loop: &EventLoopWindowTarget<UserEvent>,
         web_context: &mut WebContext,
+        sign_up_url: &str,
         application_thread_sender: Rc<Sender<Message>>,
         proxy: EventLoopProxy<UserEvent>,
     ) -> anyhow::Result<(WindowId, WebView)> {
@@ -253,7 +304,7 @@ impl<'a> Application<'a> {
 
         let webview = WebViewBuilder::new(window)
             .context("Failed to create webview builder")?
-            .with_url("https://www.roblox.com") // Signup page
+            .with_url(sign_up_url)
             .context("Failed to add url")?
             .with_user_agent("Roblox/WinInet")
             .with_hotkeys_zoom(false)
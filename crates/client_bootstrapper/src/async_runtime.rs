@@ -2,12 +2,19 @@ use std::{path::Path, process, time::Duration};
 
 use anyhow::{bail, Context};
 use crossbeam::channel::{Receiver, Sender};
+use deploy_history::domain::Channel;
 use sysinfo::{System, SystemExt};
 use tokio::time::sleep;
 
 use crate::{
-    authentication::AuthenticationContext, downloader::DownloadContext, gamejoin::GamejoinContext,
+    authentication::AuthenticationContext,
+    downloader::{
+        DownloadContext, DownloadProgress, InstalledClient, ProgressTracker, SharedStore,
+        UpdateLock, VersionCheckFailure,
+    },
+    gamejoin::GamejoinContext,
     manifest::ProjectManifest,
+    wine::Wine,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -16,6 +23,14 @@ pub enum Message {
     DownloadingClient,
     PreparingFiles,
     LaunchingGame,
+    /// Another launcher is updating the client, and this one is waiting to use what it installs.
+    WaitingForOtherLauncher,
+
+    /// Sent while the client downloads. This doesn't change the current task.
+    DownloadProgress(DownloadProgress),
+    /// Sent if the latest client version couldn't be found and the installed client is being used instead. This
+    /// doesn't change the current task.
+    VersionCheckFailed(VersionCheckFailure),
 
     PromptForAuth,
     AuthCompleted,
@@ -28,6 +43,7 @@ impl ToString for Message {
             Message::DownloadingClient => "DownloadingClient",
             Message::PreparingFiles => "PreparingFiles",
             Message::LaunchingGame => "LaunchingGame",
+            Message::WaitingForOtherLauncher => "WaitingForOtherLauncher",
             _ => "N/A",
         };
 
@@ -46,34 +62,59 @@ pub async fn initiate_application_tasks(
 ) -> anyhow::Result<()> {
     log::info!("Initiated async application tasks");
 
-    let mut download_context =
-        DownloadContext::new(root_dir).context("Failed to construct DownloadContext")?;
-    let auth_context = AuthenticationContext::new();
-    let gamejoin_context =
-        GamejoinContext::new(&auth_context).context("Failed to construct GamejoinContext")?;
-
-    log::info!("Checking for updates");
-    async_thread_sender.send(Message::CheckingForUpdates)?;
-
-    let download_required = download_context
-        .require_client_download()
-        .await
-        .context("Failed to check if download is required")?;
-
-    if download_required {
-        log::info!("Updating client");
-        async_thread_sender.send(Message::DownloadingClient)?;
-
-        download_context
-            .initiate_client_download(root_dir)
-            .await
-            .context("Failed to update client")?;
-    }
+    let endpoints = manifest.endpoints();
 
-    // FIXME: This is coupled to MacOS.
-    let roblox_player = root_dir.join("client/RobloxPlayer.app/Contents/MacOS/RobloxPlayer");
-    if !roblox_player.exists() {
-        bail!("Roblox Player does not exist at path: {roblox_player:?}");
+    let shared_store = if manifest.client.portable {
+        None
+    } else {
+        let shared_store = SharedStore::default_location();
+        if shared_store.is_none() {
+            log::warn!(
+                "There is nowhere to keep a shared client store, installing the client portably"
+            );
+        }
+        shared_store
+    };
+
+    // There's no Linux client, so the Windows one is run through Wine.
+    let wine = cfg!(target_os = "linux").then(|| Wine::new(root_dir, &manifest.wine()));
+
+    // Held until the client is updated, including while the DownloadContext tidies up after an interrupted install.
+    let update_lock = UpdateLock::acquire(root_dir, shared_store.as_ref(), || {
+        // The UI may have closed already, the launcher should carry on regardless.
+        let _ = async_thread_sender.send(Message::WaitingForOtherLauncher);
+    })
+    .await
+    .context("Failed to lock the client for updating")?;
+
+    let mut download_context = DownloadContext::new(root_dir, endpoints.clone(), &update_lock)
+        .context("Failed to construct DownloadContext")?
+        .with_checksums(manifest.client.checksums.clone())
+        .with_channel(manifest.client.channel.clone().unwrap_or(Channel::Live))
+        .with_pinned_version(manifest.client.version.clone())
+        .with_retained_versions(manifest.client.retained_versions)
+        .with_extract_limits(manifest.client.extract.clone())
+        .with_shared_store(shared_store)
+        .with_wine(wine.clone())
+        .with_retry_policy(manifest.retry.clone())
+        .with_timeouts(manifest.timeouts.clone())?;
+    download_context
+        .recover(root_dir, &update_lock)
+        .context("Failed to clean up after an interrupted client install")?;
+    let auth_context = AuthenticationContext::new();
+    let gamejoin_context = GamejoinContext::new(&auth_context, endpoints)
+        .context("Failed to construct GamejoinContext")?
+        .with_retry_policy(manifest.retry.clone())
+        .with_timeouts(manifest.timeouts.clone())?;
+
+    let roblox_player =
+        update_client(root_dir, &mut download_context, &async_thread_sender).await?;
+    drop(update_lock);
+
+    if let Some(wine) = &wine {
+        async_thread_sender.send(Message::PreparingFiles)?;
+        wine.prepare_prefix()
+            .context("Failed to prepare Wine to run the client")?;
     }
 
     // Once we have a client, make sure authentication is all good
@@ -107,10 +148,17 @@ pub async fn initiate_application_tasks(
 
     // Launch the game!
     let place_id = &manifest.game.place_id;
-    gamejoin_context
+    // Not reaching Roblox for the latest version may only mean Roblox or a mirror is down, so joining is tried
+    // regardless. If that fails too, it's most likely the player's connection.
+    let launched = gamejoin_context
         .launch_roblox_client(place_id, &roblox_player)
         .await
-        .context("Failed to launch Roblox client")?;
+        .context("Failed to launch Roblox client");
+    if download_context.version_check_failure() == Some(VersionCheckFailure::NoNetwork) {
+        launched.context("Couldn't reach Roblox, check your internet connection")?;
+    } else {
+        launched?;
+    }
 
     // Wait until the Roblox player has started and exit this process
     while System::new_all()
@@ -127,3 +175,59 @@ pub async fn initiate_application_tasks(
     sleep(Duration::from_secs(2)).await;
     process::exit(0);
 }
+
+/// Makes sure the latest client is installed, downloading it if it's missing or out of date. Returns the installed
+/// client, to launch the player from.
+///
+/// If the latest version can't be found, the installed client is used as it is and the UI is warned with
+/// `Message::VersionCheckFailed`.
+pub async fn update_client(
+    root_dir: &Path,
+    download_context: &mut DownloadContext,
+    async_thread_sender: &Sender<Message>,
+) -> anyhow::Result<InstalledClient> {
+    log::info!("Checking for updates");
+    async_thread_sender.send(Message::CheckingForUpdates)?;
+
+    let download_required = download_context
+        .require_client_download()
+        .await
+        .context("Failed to check if download is required")?;
+
+    if let Some(failure) = download_context.version_check_failure() {
+        async_thread_sender.send(Message::VersionCheckFailed(failure))?;
+    }
+
+    if download_required {
+        log::info!("Updating client");
+        async_thread_sender.send(Message::DownloadingClient)?;
+
+        let progress_sender = async_thread_sender.clone();
+        let progress = ProgressTracker::new(move |progress| {
+            // The UI may have closed already, the download should carry on regardless.
+            let _ = progress_sender.send(Message::DownloadProgress(progress));
+        });
+
+        download_context
+            .initiate_client_download(root_dir, &progress)
+            .await
+            .context("Failed to update client")?;
+    }
+
+    let mut roblox_player = download_context.installed_client(root_dir)?;
+    if download_required && !roblox_player.executable.exists() {
+        log::warn!("Updated client is missing Roblox Player, falling back to the previous client");
+        download_context
+            .roll_back(root_dir)
+            .context("Updated client is broken")?;
+
+        roblox_player = download_context.installed_client(root_dir)?;
+    }
+
+    let executable = &roblox_player.executable;
+    if !executable.exists() {
+        bail!("Roblox Player does not exist at path: {executable:?}");
+    }
+
+    Ok(roblox_player)
+}
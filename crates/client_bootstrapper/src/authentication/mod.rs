@@ -1,14 +1,19 @@
+#[cfg(target_os = "macos")]
 use std::fs;
 
-use anyhow::{bail, Context};
+use anyhow::bail;
+#[cfg(target_os = "macos")]
+use anyhow::Context;
 use cookie::Cookie;
 use secrecy::{ExposeSecret, SecretString};
 
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 mod utils;
 
 #[cfg(target_os = "macos")]
 mod binarycookies;
 
+#[cfg(target_os = "macos")]
 static COOKIE_NAME: &str = ".ROBLOSECURITY";
 
 /// Stores state about Roblox account authentication and handles all behavior around getting authenticated.
@@ -39,10 +44,11 @@ impl AuthenticationContext {
         Ok(cookie)
     }
 
-    pub fn get_roblosecurity_cookie(&self) -> anyhow::Result<Cookie> {
+    /// The `.ROBLOSECURITY` cookie, set for `domain`.
+    pub fn get_roblosecurity_cookie(&self, domain: &str) -> anyhow::Result<Cookie> {
         if let Some(cookie_str) = self.get_roblosecurity()? {
             let cookie = Cookie::build(".ROBLOSECURITY", cookie_str.expose_secret().to_owned())
-                .domain(".roblox.com")
+                .domain(domain.to_owned())
                 .finish();
 
             Ok(cookie)
@@ -80,4 +86,11 @@ impl AuthenticationContext {
     pub fn get_webview_roblosecurity(&self) -> anyhow::Result<SecretString> {
         todo!()
     }
+
+    /// There's no WebView cookie storage we know how to read on other platforms, so authentication has to come from
+    /// `rbx_cookie` (e.g. the `ROBLOSECURITY` environment variable).
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    pub fn get_webview_roblosecurity(&self) -> anyhow::Result<Option<SecretString>> {
+        Ok(None)
+    }
 }
@@ -1,33 +1,613 @@
-use std::{fs, path::Path};
+//! `client/client.lock` records every client version installed for the app, and which of them gets launched.
+//!
+//! The lock has a schema version so older locks can be migrated when they're loaded:
+//!  0. `version = { .. }`, a single version with its files directly in `client/`.
+//!  1. `current` and `versions`, each installed in `<store>/<version_guid>/`, but no `schema_version`.
+//!  2. Adds `schema_version`, `binary_type`, and when, where from and with which files each version was installed.
+//!     Later also `broken_versions`, which older schema 2 locks are read without.
 
-use anyhow::Context;
-use deploy_history::client_version_info::ClientVersionInfo;
+use std::{
+    collections::BTreeMap,
+    fs, mem,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+use deploy_history::{client_version_info::ClientVersionInfo, domain::BinaryType};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::{install, integrity, update_lock::UPDATE_LOCK_FILE};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+const LOCK_FILE: &str = "client.lock";
+
+/// Every client version installed in `client/`, and which of them gets launched.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ClientLock {
+    pub schema_version: u32,
+    pub binary_type: BinaryType,
+    /// The `version_guid` of the version to launch.
+    pub current: String,
+    /// The shared store directory the versions are installed in, or `None` if they're installed in `client/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// The `version_guid`s of the versions rolled back from, which aren't installed again until a different version
+    /// is installed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub broken_versions: Vec<String>,
+    /// Oldest install first. Each version is installed in `<store>/<version_guid>/`.
+    #[serde(default)]
+    pub versions: Vec<InstalledVersion>,
+}
+
+// Plain values come before tables, otherwise the lock can't be written as TOML.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstalledVersion {
+    /// When the version was installed, in seconds since the Unix epoch.
+    pub installed_at: u64,
+    /// Where the version's packages were downloaded from. Empty if another app had already put it in the shared
+    /// store, or it was installed before these were recorded.
+    #[serde(default)]
+    pub source_urls: Vec<String>,
+    /// Also records the channel the version was installed from.
     pub version: ClientVersionInfo,
+    /// SHA-256 of every file in the version, by its path relative to the version directory.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+/// Schema 0.
+#[derive(Debug, Deserialize)]
+struct LegacyLock {
+    version: ClientVersionInfo,
+}
+
+/// Schema 1.
+#[derive(Debug, Deserialize)]
+struct UnversionedLock {
+    current: String,
+    #[serde(default)]
+    store: Option<PathBuf>,
+    #[serde(default)]
+    versions: Vec<UnversionedInstalledVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnversionedInstalledVersion {
+    version: ClientVersionInfo,
+}
+
+impl InstalledVersion {
+    /// A version installed just now.
+    pub fn new(
+        version: ClientVersionInfo,
+        source_urls: Vec<String>,
+        files: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            installed_at: unix_time(SystemTime::now()),
+            source_urls,
+            version,
+            files,
+        }
+    }
 }
 
 impl ClientLock {
-    pub fn get(root_dir: &Path) -> anyhow::Result<ClientLock> {
-        let lock_path = root_dir.join("client/client.lock");
+    /// A lock with just `installed` installed, in the shared store directory `store` if there is one.
+    pub fn new(
+        binary_type: BinaryType,
+        installed: InstalledVersion,
+        store: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            binary_type,
+            current: installed.version.version_guid.to_owned(),
+            store,
+            broken_versions: Vec::new(),
+            versions: vec![installed],
+        }
+    }
 
-        let lock = fs::read_to_string(lock_path).context("Failed to read client.lock")?;
-        let lock = toml::from_str::<ClientLock>(&lock)
+    /// Read the lock in `root_dir`, or `None` if there isn't one. Errors if the lock is corrupt, or hasn't been
+    /// migrated to the current schema yet.
+    pub fn get(root_dir: &Path) -> anyhow::Result<Option<ClientLock>> {
+        let Some(lock) = read_lock(root_dir)? else {
+            return Ok(None);
+        };
+
+        let schema_version = schema_version(&lock)?;
+        if schema_version != SCHEMA_VERSION {
+            bail!("client.lock has schema version {schema_version}, expected {SCHEMA_VERSION}");
+        }
+
+        let lock = lock
+            .try_into::<ClientLock>()
             .context("Failed to parse client.lock to ClientLock format")?;
 
-        Ok(lock)
+        Ok(Some(lock))
+    }
+
+    /// Read the lock in `root_dir` like `get`, migrating it to the current schema first if it's older. Locks from
+    /// before the binary type was recorded are assumed to be for `binary_type`.
+    pub fn load(root_dir: &Path, binary_type: &BinaryType) -> anyhow::Result<Option<ClientLock>> {
+        let Some(lock) = read_lock(root_dir)? else {
+            return Ok(None);
+        };
+
+        let lock = match schema_version(&lock)? {
+            0 => {
+                let lock = lock
+                    .try_into::<LegacyLock>()
+                    .context("Failed to parse client.lock with schema version 0")?;
+                migrate_legacy(root_dir, binary_type, lock)?
+            }
+            1 => {
+                let lock = lock
+                    .try_into::<UnversionedLock>()
+                    .context("Failed to parse client.lock with schema version 1")?;
+                migrate_unversioned(root_dir, binary_type, lock)?
+            }
+            SCHEMA_VERSION => {
+                return lock
+                    .try_into::<ClientLock>()
+                    .context("Failed to parse client.lock to ClientLock format")
+                    .map(Some);
+            }
+            schema_version => {
+                bail!("client.lock has schema version {schema_version}, which is too new")
+            }
+        };
+
+        log::info!("Migrated client.lock to schema version {SCHEMA_VERSION}");
+        lock.write_lock_to_path(root_dir)
+            .context("Failed to write migrated client.lock")?;
+
+        Ok(Some(lock))
     }
 
-    pub fn write_lock_to_path(&self, lock: &ClientLock, root_dir: &Path) -> anyhow::Result<()> {
-        let lock_path = root_dir.join("client/client.lock");
+    /// Move an unreadable lock out of the way, keeping it beside the new one to look into later.
+    pub fn set_aside(root_dir: &Path) -> anyhow::Result<PathBuf> {
+        let lock_path = root_dir.join(install::CLIENT_DIR).join(LOCK_FILE);
+        let corrupt_path = lock_path.with_extension("lock.corrupt");
+
+        fs::rename(&lock_path, &corrupt_path).context(format!(
+            "Failed to move corrupt client.lock to {corrupt_path:?}"
+        ))?;
+
+        Ok(corrupt_path)
+    }
+
+    /// The lock is written beside itself then renamed over the old one, so it's never left half written.
+    pub fn write_lock_to_path(&self, root_dir: &Path) -> anyhow::Result<()> {
+        let client_dir = root_dir.join(install::CLIENT_DIR);
+        fs::create_dir_all(&client_dir)
+            .context(format!("Failed to create client directory {client_dir:?}"))?;
+
+        let lock_path = client_dir.join(LOCK_FILE);
+        let temp_path = lock_path.with_extension("lock.tmp");
 
         let lock =
-            toml::to_string_pretty(lock).context("Failed to convert ClientLock to string")?;
+            toml::to_string_pretty(self).context("Failed to convert ClientLock to string")?;
 
-        fs::write(lock_path, lock).context("Failed to write client.lock")?;
+        fs::write(&temp_path, lock).context("Failed to write client.lock")?;
+        fs::rename(&temp_path, &lock_path).context("Failed to replace client.lock")?;
 
         Ok(())
     }
+
+    pub fn current(&self) -> Option<&InstalledVersion> {
+        self.installed(&self.current)
+    }
+
+    /// Where the versions are installed.
+    pub fn store_dir(&self, root_dir: &Path) -> PathBuf {
+        self.store
+            .clone()
+            .unwrap_or_else(|| root_dir.join(install::CLIENT_DIR))
+    }
+
+    /// Where the current version is installed.
+    pub fn current_dir(&self, root_dir: &Path) -> PathBuf {
+        install::version_dir(&self.store_dir(root_dir), &self.current)
+    }
+
+    pub fn installed(&self, version_guid: &str) -> Option<&InstalledVersion> {
+        self.versions
+            .iter()
+            .find(|installed| installed.version.version_guid == version_guid)
+    }
+
+    /// The most recently installed version, other than the current one.
+    pub fn previous(&self) -> Option<&InstalledVersion> {
+        self.versions
+            .iter()
+            .rev()
+            .find(|installed| installed.version.version_guid != self.current)
+    }
+
+    /// Record `installed` as installed, and make it the current version. A different version is out, so the versions
+    /// rolled back from are forgotten.
+    pub fn install(&mut self, installed: InstalledVersion) {
+        self.remove(&installed.version.version_guid);
+        self.current = installed.version.version_guid.to_owned();
+        self.versions.push(installed);
+        self.broken_versions.clear();
+    }
+
+    /// Whether `version_guid` was rolled back from.
+    pub fn is_broken(&self, version_guid: &str) -> bool {
+        self.broken_versions
+            .iter()
+            .any(|broken| broken == version_guid)
+    }
+
+    pub fn remove(&mut self, version_guid: &str) -> Option<InstalledVersion> {
+        let index = self
+            .versions
+            .iter()
+            .position(|installed| installed.version.version_guid == version_guid)?;
+
+        Some(self.versions.remove(index))
+    }
+
+    /// Forget the versions which are no longer installed, returning them. If the current version is one of them, the
+    /// most recently installed version left becomes current.
+    pub fn forget_missing(&mut self, root_dir: &Path) -> Vec<InstalledVersion> {
+        let store_dir = self.store_dir(root_dir);
+        let (installed, missing) =
+            mem::take(&mut self.versions)
+                .into_iter()
+                .partition(|installed| {
+                    install::version_dir(&store_dir, &installed.version.version_guid).exists()
+                });
+        self.versions = installed;
+
+        if self.current().is_none() {
+            if let Some(latest) = self.versions.last() {
+                self.current = latest.version.version_guid.to_owned();
+            }
+        }
+
+        missing
+    }
+
+    /// Forget the oldest versions until at most `retain` are left, returning the ones forgotten. The current version
+    /// is always kept.
+    pub fn prune(&mut self, retain: usize) -> Vec<InstalledVersion> {
+        let mut pruned = Vec::new();
+
+        while self.versions.len() > retain.max(1) {
+            let Some(index) = self
+                .versions
+                .iter()
+                .position(|installed| installed.version.version_guid != self.current)
+            else {
+                break;
+            };
+
+            pruned.push(self.versions.remove(index));
+        }
+
+        pruned
+    }
+}
+
+fn read_lock(root_dir: &Path) -> anyhow::Result<Option<toml::Value>> {
+    let lock_path = root_dir.join(install::CLIENT_DIR).join(LOCK_FILE);
+    if !lock_path.exists() {
+        return Ok(None);
+    }
+
+    let lock = fs::read_to_string(lock_path).context("Failed to read client.lock")?;
+    let lock = toml::from_str::<toml::Value>(&lock).context("client.lock isn't valid TOML")?;
+
+    Ok(Some(lock))
+}
+
+fn schema_version(lock: &toml::Value) -> anyhow::Result<u32> {
+    match lock.get("schema_version") {
+        Some(schema_version) => schema_version
+            .as_integer()
+            .and_then(|schema_version| u32::try_from(schema_version).ok())
+            .context("client.lock has a malformed schema version"),
+        None if lock.get("version").is_some() => Ok(0),
+        None => Ok(1),
+    }
+}
+
+/// The client's files are moved out of `client/` into a directory for its version. This is safe to repeat if it's
+/// interrupted, since the lock isn't replaced until every file has been moved.
+fn migrate_legacy(
+    root_dir: &Path,
+    binary_type: &BinaryType,
+    lock: LegacyLock,
+) -> anyhow::Result<ClientLock> {
+    let client_dir = root_dir.join(install::CLIENT_DIR);
+    let version_guid = &lock.version.version_guid;
+    let version_dir = install::version_dir(&client_dir, version_guid);
+
+    fs::create_dir_all(&version_dir)
+        .context(format!("Failed to create client directory {version_dir:?}"))?;
+
+    let entries = fs::read_dir(&client_dir)
+        .context(format!("Failed to read client directory {client_dir:?}"))?;
+    for entry in entries {
+        let entry = entry.context("Failed to read client directory entry")?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        // The update lock is held while this runs.
+        if file_name.starts_with(LOCK_FILE)
+            || file_name == UPDATE_LOCK_FILE
+            || file_name == version_guid.as_str()
+        {
+            continue;
+        }
+
+        let path = entry.path();
+        fs::rename(&path, version_dir.join(entry.file_name()))
+            .context(format!("Failed to move {path:?} into {version_dir:?}"))?;
+    }
+
+    // There's nothing to check the files against any more, so they're trusted as they are.
+    let files =
+        integrity::record_client_hashes(&version_dir).context("Failed to record client hashes")?;
+
+    let installed = InstalledVersion {
+        installed_at: modified_at(&client_dir.join(LOCK_FILE)),
+        source_urls: Vec::new(),
+        version: lock.version,
+        files,
+    };
+
+    Ok(ClientLock::new(binary_type.to_owned(), installed, None))
+}
+
+fn migrate_unversioned(
+    root_dir: &Path,
+    binary_type: &BinaryType,
+    lock: UnversionedLock,
+) -> anyhow::Result<ClientLock> {
+    let mut migrated = ClientLock {
+        schema_version: SCHEMA_VERSION,
+        binary_type: binary_type.to_owned(),
+        current: lock.current,
+        store: lock.store,
+        broken_versions: Vec::new(),
+        versions: Vec::new(),
+    };
+    let store_dir = migrated.store_dir(root_dir);
+
+    for installed in lock.versions {
+        let version_dir = install::version_dir(&store_dir, &installed.version.version_guid);
+
+        // Hashes have been recorded beside each version since before the lock had a schema.
+        let files = integrity::read_client_hashes(&version_dir).unwrap_or_else(|error| {
+            log::warn!(
+                "Failed to read hashes of client {}: {error:?}",
+                installed.version
+            );
+            BTreeMap::new()
+        });
+
+        migrated.versions.push(InstalledVersion {
+            installed_at: modified_at(&version_dir),
+            source_urls: Vec::new(),
+            version: installed.version,
+            files,
+        });
+    }
+
+    Ok(migrated)
+}
+
+/// When `path` was last modified, as a stand in for when something was installed. Zero if it can't be found.
+fn modified_at(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(unix_time)
+        .unwrap_or_default()
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs};
+
+    use deploy_history::{
+        client_version_info::ClientVersionInfo,
+        domain::{BinaryType, Channel},
+    };
+    use tempfile::TempDir;
+
+    use crate::downloader::client_lock::{ClientLock, InstalledVersion, SCHEMA_VERSION};
+
+    fn get_version(version: &str, version_guid: &str) -> InstalledVersion {
+        let version =
+            ClientVersionInfo::new(Channel::Live, version.into(), version_guid.into()).unwrap();
+        InstalledVersion::new(version, Vec::new(), BTreeMap::new())
+    }
+
+    fn new_lock(version: &str, version_guid: &str) -> ClientLock {
+        ClientLock::new(
+            BinaryType::MacPlayer,
+            get_version(version, version_guid),
+            None,
+        )
+    }
+
+    fn guids(lock: &ClientLock) -> Vec<&str> {
+        lock.versions
+            .iter()
+            .map(|installed| installed.version.version_guid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn tracks_installed_versions() {
+        let mut lock = new_lock("0.553.0.5530470", "version-a");
+        lock.install(get_version("0.554.0.5540123", "version-b"));
+        lock.install(get_version("0.555.0.5550001", "version-c"));
+
+        assert_eq!(lock.current, "version-c");
+        assert_eq!(lock.previous().unwrap().version.version_guid, "version-b");
+
+        // Reinstalling a version moves it to the back.
+        lock.install(get_version("0.553.0.5530470", "version-a"));
+        assert_eq!(guids(&lock), ["version-b", "version-c", "version-a"]);
+        assert_eq!(lock.previous().unwrap().version.version_guid, "version-c");
+    }
+
+    #[test]
+    fn prunes_oldest_versions() {
+        let mut lock = new_lock("0.553.0.5530470", "version-a");
+        lock.install(get_version("0.554.0.5540123", "version-b"));
+        lock.install(get_version("0.555.0.5550001", "version-c"));
+        lock.current = "version-a".into();
+
+        let pruned = lock.prune(2);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].version.version_guid, "version-b");
+        assert_eq!(guids(&lock), ["version-a", "version-c"]);
+
+        // The current version is kept, even if nothing else is.
+        lock.prune(0);
+        assert_eq!(guids(&lock), ["version-a"]);
+    }
+
+    #[test]
+    fn round_trips_through_disk() {
+        let root_dir = TempDir::new().unwrap();
+        let mut lock = new_lock("0.553.0.5530470", "version-a");
+        let mut installed = get_version("0.554.0.5540123", "version-b");
+        installed.source_urls =
+            vec!["https://setup.rbxcdn.com/mac/version-b-RobloxPlayer.zip".into()];
+        installed
+            .files
+            .insert("RobloxPlayer".into(), "2cf24dba".into());
+        lock.install(installed);
+        lock.broken_versions.push("version-c".into());
+
+        lock.write_lock_to_path(root_dir.path()).unwrap();
+        let parsed = ClientLock::get(root_dir.path()).unwrap().unwrap();
+
+        assert_eq!(parsed.schema_version, SCHEMA_VERSION);
+        assert_eq!(parsed.binary_type, BinaryType::MacPlayer);
+        assert_eq!(parsed.current, "version-b");
+        assert_eq!(guids(&parsed), ["version-a", "version-b"]);
+        assert!(parsed.is_broken("version-c"));
+
+        let current = parsed.current().unwrap();
+        assert_eq!(current.source_urls.len(), 1);
+        assert_eq!(current.files["RobloxPlayer"], "2cf24dba");
+        assert!(current.installed_at > 0);
+    }
+
+    #[test]
+    fn migrates_legacy_lock() {
+        let root_dir = TempDir::new().unwrap();
+        let client_dir = root_dir.path().join("client");
+        fs::create_dir_all(client_dir.join("RobloxPlayer.app/Contents/MacOS")).unwrap();
+        fs::write(
+            client_dir.join("RobloxPlayer.app/Contents/MacOS/RobloxPlayer"),
+            "player",
+        )
+        .unwrap();
+        fs::write(
+            client_dir.join("client.lock"),
+            r#"
+            [version]
+            channel = "Live"
+            version_guid = "version-d780cbcde4ab4f52"
+            major_rev = 0
+            version = 553
+            patch = 0
+            change_list = 5530470
+            "#,
+        )
+        .unwrap();
+
+        // The lock can't be used until it's migrated.
+        assert!(ClientLock::get(root_dir.path()).is_err());
+
+        let lock = ClientLock::load(root_dir.path(), &BinaryType::MacPlayer)
+            .unwrap()
+            .unwrap();
+        assert_eq!(lock.current, "version-d780cbcde4ab4f52");
+        assert_eq!(lock.binary_type, BinaryType::MacPlayer);
+
+        let player = "RobloxPlayer.app/Contents/MacOS/RobloxPlayer";
+        assert_eq!(
+            fs::read_to_string(lock.current_dir(root_dir.path()).join(player)).unwrap(),
+            "player"
+        );
+        assert!(!client_dir.join("RobloxPlayer.app").exists());
+        assert!(lock.current().unwrap().files.contains_key(player));
+
+        let migrated = ClientLock::get(root_dir.path()).unwrap().unwrap();
+        assert_eq!(migrated.current, "version-d780cbcde4ab4f52");
+    }
+
+    #[test]
+    fn migrates_unversioned_lock() {
+        let root_dir = TempDir::new().unwrap();
+        let client_dir = root_dir.path().join("client");
+        fs::create_dir_all(client_dir.join("version-a")).unwrap();
+        fs::write(
+            client_dir.join("version-a/client.hashes"),
+            "[files]\nRobloxPlayer = \"2cf24dba\"\n",
+        )
+        .unwrap();
+        fs::write(
+            client_dir.join("client.lock"),
+            r#"
+            current = "version-a"
+
+            [[versions]]
+            [versions.version]
+            channel = "live"
+            version_guid = "version-a"
+            major_rev = 0
+            version = 553
+            patch = 0
+            change_list = 5530470
+            "#,
+        )
+        .unwrap();
+
+        let lock = ClientLock::load(root_dir.path(), &BinaryType::WindowsPlayer)
+            .unwrap()
+            .unwrap();
+        assert_eq!(lock.schema_version, SCHEMA_VERSION);
+        assert_eq!(lock.binary_type, BinaryType::WindowsPlayer);
+        assert_eq!(guids(&lock), ["version-a"]);
+        assert_eq!(lock.current().unwrap().files["RobloxPlayer"], "2cf24dba");
+    }
+
+    #[test]
+    fn rejects_unreadable_locks() {
+        let root_dir = TempDir::new().unwrap();
+        assert!(ClientLock::get(root_dir.path()).unwrap().is_none());
+
+        let lock_path = root_dir.path().join("client/client.lock");
+        fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+
+        fs::write(&lock_path, "current = ").unwrap();
+        assert!(ClientLock::load(root_dir.path(), &BinaryType::MacPlayer).is_err());
+
+        fs::write(&lock_path, "schema_version = 3\ncurrent = \"version-a\"").unwrap();
+        assert!(ClientLock::load(root_dir.path(), &BinaryType::MacPlayer).is_err());
+
+        let corrupt_path = ClientLock::set_aside(root_dir.path()).unwrap();
+        assert!(!lock_path.exists());
+        assert!(corrupt_path.exists());
+    }
 }
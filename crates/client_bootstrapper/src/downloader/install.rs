@@ -0,0 +1,146 @@
+//! Where client versions are installed, and how a freshly downloaded one is put in place.
+//!
+//! Each version is installed in `<store>/<version_guid>/`, where the store is either the app's own `client/`
+//! directory or a shared store (see `store`). A new version is built in `<store>/<version_guid>.staging/` and renamed
+//! into place once it's been fully downloaded and checked, so a version directory is never left half written. The
+//! version that gets launched is recorded in `client/client.lock`, which is only pointed at a new version once it's in
+//! place. Anything older stays installed to fall back on, until it's pruned.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+pub const CLIENT_DIR: &str = "client";
+/// Where packages are downloaded to before they're installed, relative to the root directory.
+pub const DOWNLOADS_DIR: &str = "downloads";
+const STAGING_EXTENSION: &str = "staging";
+
+/// Where the client version `version_guid` is installed in `store_dir`.
+pub fn version_dir(store_dir: &Path, version_guid: &str) -> PathBuf {
+    store_dir.join(version_guid)
+}
+
+fn staging_dir(store_dir: &Path, version_guid: &str) -> PathBuf {
+    store_dir.join(format!("{version_guid}.{STAGING_EXTENSION}"))
+}
+
+/// Create an empty directory to build `version_guid` in, clearing out anything left from an earlier attempt.
+pub fn prepare_staging(store_dir: &Path, version_guid: &str) -> anyhow::Result<PathBuf> {
+    let staging_dir = staging_dir(store_dir, version_guid);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir).context(format!(
+            "Failed to delete old staging directory {staging_dir:?}"
+        ))?;
+    }
+
+    fs::create_dir_all(&staging_dir).context(format!(
+        "Failed to create staging directory {staging_dir:?}"
+    ))?;
+
+    Ok(staging_dir)
+}
+
+/// Move the staged `version_guid` into place, replacing any earlier install of the same version.
+pub fn swap_in_staged(store_dir: &Path, version_guid: &str) -> anyhow::Result<()> {
+    let staging_dir = staging_dir(store_dir, version_guid);
+    let version_dir = version_dir(store_dir, version_guid);
+
+    remove_version(store_dir, version_guid)?;
+
+    fs::rename(&staging_dir, &version_dir)
+        .context(format!("Failed to move new client into {version_dir:?}"))?;
+
+    log::info!("Installed new client into {version_dir:?}");
+
+    Ok(())
+}
+
+/// Delete the installed `version_guid`, if it's installed.
+pub fn remove_version(store_dir: &Path, version_guid: &str) -> anyhow::Result<()> {
+    let version_dir = version_dir(store_dir, version_guid);
+    if version_dir.exists() {
+        fs::remove_dir_all(&version_dir)
+            .context(format!("Failed to delete client {version_dir:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Tidy up after a launcher which stopped part way through installing a client. Staged clients may not be complete,
+/// so they're deleted.
+pub fn recover(store_dir: &Path) -> anyhow::Result<()> {
+    let Ok(entries) = fs::read_dir(store_dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == STAGING_EXTENSION)
+        {
+            log::info!("Removing unfinished client install {path:?}");
+            fs::remove_dir_all(&path).context(format!(
+                "Failed to delete unfinished client install {path:?}"
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::TempDir;
+
+    use crate::downloader::install::{
+        prepare_staging, recover, remove_version, swap_in_staged, version_dir,
+    };
+
+    fn write_client(dir: &Path, contents: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("player"), contents).unwrap();
+    }
+
+    fn read_client(dir: &Path) -> String {
+        fs::read_to_string(dir.join("player")).unwrap()
+    }
+
+    #[test]
+    fn swaps_in_staged_versions() {
+        let store_dir = TempDir::new().unwrap();
+        let store_dir = store_dir.path();
+        write_client(&version_dir(store_dir, "version-a"), "old");
+        write_client(&version_dir(store_dir, "version-b"), "broken");
+
+        let staging_dir = prepare_staging(store_dir, "version-b").unwrap();
+        write_client(&staging_dir, "new");
+        swap_in_staged(store_dir, "version-b").unwrap();
+
+        assert_eq!(read_client(&version_dir(store_dir, "version-a")), "old");
+        assert_eq!(read_client(&version_dir(store_dir, "version-b")), "new");
+        assert!(!staging_dir.exists());
+
+        remove_version(store_dir, "version-b").unwrap();
+        assert!(!version_dir(store_dir, "version-b").exists());
+    }
+
+    #[test]
+    fn recovers_interrupted_install() {
+        let store_dir = TempDir::new().unwrap();
+        let store_dir = store_dir.path();
+        write_client(&version_dir(store_dir, "version-a"), "old");
+        let staging_dir = prepare_staging(store_dir, "version-b").unwrap();
+        write_client(&staging_dir, "half downloaded");
+
+        recover(store_dir).unwrap();
+
+        assert_eq!(read_client(&version_dir(store_dir, "version-a")), "old");
+        assert!(!staging_dir.exists());
+    }
+}
@@ -0,0 +1,253 @@
+//! Checks that downloaded packages and installed clients are exactly what Roblox published.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::client_lock::ClientLock;
+
+/// Where the hashes of an installed client's files are recorded, relative to the client directory.
+const HASHES_FILE: &str = "client.hashes";
+
+/// Files in the client directory which aren't part of the client itself.
+const UNTRACKED_FILES: [&str; 2] = ["client.lock", HASHES_FILE];
+
+/// An expected checksum of a file, as a lowercase hex string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// Roblox publishes MD5 checksums for Windows packages in `rbxPkgManifest.txt`.
+    Md5(String),
+    Sha256(String),
+}
+
+impl Checksum {
+    /// Errors if the file at `path` doesn't match this checksum.
+    pub fn verify(&self, path: &Path) -> anyhow::Result<()> {
+        let (algorithm, expected, actual) = match self {
+            Checksum::Md5(expected) => ("MD5", expected, hash_file::<Md5>(path)),
+            Checksum::Sha256(expected) => ("SHA-256", expected, hash_file::<Sha256>(path)),
+        };
+
+        let actual = actual.context(format!("Failed to hash {path:?}"))?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            bail!("{algorithm} of {path:?} is {actual}, expected {expected}");
+        }
+
+        Ok(())
+    }
+}
+
+/// The result of checking an installed client against the hashes recorded when it was installed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ClientVerification {
+    /// Files which were installed but no longer exist.
+    pub missing: Vec<PathBuf>,
+    /// Files whose contents have changed since they were installed.
+    pub modified: Vec<PathBuf>,
+}
+
+impl ClientVerification {
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ClientHashes {
+    /// SHA-256 of every file in the client, by its path relative to the client directory.
+    files: BTreeMap<String, String>,
+}
+
+/// Record the hashes of every file in the client directory beside it, so the client can be verified later. Returns
+/// the hashes, by path relative to the client directory.
+pub fn record_client_hashes(client_dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut hashes = ClientHashes::default();
+
+    for relative_path in client_files(client_dir).context("Failed to list client files")? {
+        let path = client_dir.join(&relative_path);
+        let hash = hash_file::<Sha256>(&path).context(format!("Failed to hash {path:?}"))?;
+        hashes.files.insert(relative_path, hash);
+    }
+
+    let contents = toml::to_string(&hashes).context("Failed to convert client hashes to string")?;
+    fs::write(client_dir.join(HASHES_FILE), contents).context("Failed to write client hashes")?;
+
+    Ok(hashes.files)
+}
+
+/// Read the hashes recorded beside a client by `record_client_hashes`, e.g. to adopt a client another app installed.
+pub fn read_client_hashes(client_dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let hashes_path = client_dir.join(HASHES_FILE);
+    let hashes = fs::read_to_string(&hashes_path)
+        .context(format!("Failed to read client hashes at {hashes_path:?}"))?;
+    let hashes =
+        toml::from_str::<ClientHashes>(&hashes).context("Failed to parse client hashes")?;
+
+    Ok(hashes.files)
+}
+
+/// Recheck the current client installed in `root_dir` against the hashes recorded in `client.lock` when it was
+/// installed.
+pub fn verify_client(root_dir: &Path) -> anyhow::Result<ClientVerification> {
+    let lock = ClientLock::get(root_dir)
+        .context("Failed to read client.lock")?
+        .context("There is no installed client")?;
+    let installed = lock
+        .current()
+        .context("The current client isn't recorded in client.lock")?;
+
+    if installed.files.is_empty() {
+        bail!(
+            "No file hashes were recorded when client {} was installed",
+            installed.version
+        );
+    }
+
+    verify_client_files(&lock.current_dir(root_dir), &installed.files)
+}
+
+fn verify_client_files(
+    client_dir: &Path,
+    files: &BTreeMap<String, String>,
+) -> anyhow::Result<ClientVerification> {
+    let mut verification = ClientVerification::default();
+
+    for (relative_path, expected) in files {
+        let path = client_dir.join(relative_path);
+
+        match hash_file::<Sha256>(&path) {
+            Ok(actual) if actual == *expected => {}
+            Ok(_) => verification.modified.push(path),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                verification.missing.push(path)
+            }
+            Err(error) => return Err(error).context(format!("Failed to hash {path:?}")),
+        }
+    }
+
+    Ok(verification)
+}
+
+/// Hash a file without reading it all into memory.
+fn hash_file<D: Digest + Write>(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = D::new();
+    io::copy(&mut file, &mut hasher)?;
+
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    Ok(hash)
+}
+
+/// Every regular file in the client directory, relative to it with `/` separators. Symlinks are skipped, they're
+/// only ever links to other files in the client.
+fn client_files(client_dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut directories = vec![client_dir.to_owned()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+
+            if file_type.is_dir() {
+                directories.push(path);
+            } else if file_type.is_file() {
+                let relative_path = path
+                    .strip_prefix(client_dir)
+                    .expect("path is inside the client directory")
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                if !UNTRACKED_FILES.contains(&relative_path.as_str()) {
+                    files.push(relative_path);
+                }
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use crate::downloader::integrity::{
+        read_client_hashes, record_client_hashes, verify_client_files, Checksum,
+    };
+
+    #[test]
+    fn verifies_checksums() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("package.zip");
+        fs::write(&path, "hello").unwrap();
+
+        assert!(Checksum::Md5("5d41402abc4b2a76b9719d911017c592".into())
+            .verify(&path)
+            .is_ok());
+        assert!(Checksum::Sha256(
+            "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824".into()
+        )
+        .verify(&path)
+        .is_ok());
+        assert!(Checksum::Md5("00000000000000000000000000000000".into())
+            .verify(&path)
+            .is_err());
+    }
+
+    #[test]
+    fn verifies_installed_client() {
+        let root_dir = TempDir::new().unwrap();
+        let client_dir = root_dir.path().join("client");
+        fs::create_dir_all(client_dir.join("content/fonts")).unwrap();
+        fs::write(client_dir.join("RobloxPlayerBeta.exe"), "player").unwrap();
+        fs::write(client_dir.join("content/fonts/Arial.ttf"), "font").unwrap();
+        fs::write(client_dir.join("client.lock"), "lock").unwrap();
+
+        let hashes = record_client_hashes(&client_dir).unwrap();
+        assert_eq!(read_client_hashes(&client_dir).unwrap(), hashes);
+        assert!(verify_client_files(&client_dir, &hashes)
+            .unwrap()
+            .is_intact());
+
+        // The lock isn't part of the client, so it can change freely.
+        fs::write(client_dir.join("client.lock"), "new lock").unwrap();
+        fs::write(client_dir.join("RobloxPlayerBeta.exe"), "tampered").unwrap();
+        fs::remove_file(client_dir.join("content/fonts/Arial.ttf")).unwrap();
+
+        let verification = verify_client_files(&client_dir, &hashes).unwrap();
+        assert_eq!(
+            verification.modified,
+            [client_dir.join("RobloxPlayerBeta.exe")]
+        );
+        assert_eq!(
+            verification.missing,
+            [client_dir.join("content/fonts/Arial.ttf")]
+        );
+    }
+
+    #[test]
+    fn errors_without_recorded_hashes() {
+        let root_dir = TempDir::new().unwrap();
+        assert!(read_client_hashes(&root_dir.path().join("client")).is_err());
+    }
+}
@@ -1,16 +1,45 @@
-use std::path::Path;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Context;
-use deploy_history::client_version_info::ClientVersionInfo;
+use anyhow::{bail, Context};
+use deploy_history::{
+    cache::{HttpCache, StaleResponse, Staleness},
+    client_version_info::ClientVersionInfo,
+    domain::{BinaryType, Channel},
+    endpoints::Endpoints,
+    get_deploy_logs_for_channel,
+    retry::RetryPolicy,
+};
 use reqwest::Client;
 
-use crate::downloader::platform_impl::ClientDownloader;
+use crate::{
+    downloader::{integrity::Checksum, platform_impl::ClientDownloader},
+    http::Timeouts,
+    wine::Wine,
+};
 
-use self::client_lock::ClientLock;
-use self::platform_impl::Downloader;
+use self::client_lock::{ClientLock, InstalledVersion};
+
+pub use self::{
+    integrity::{verify_client, ClientVerification},
+    platform_impl::{ExtractError, ExtractLimits, InstalledClient},
+    progress::{DownloadProgress, ProgressTracker},
+    store::SharedStore,
+    update_lock::UpdateLock,
+};
 
 mod client_lock;
+mod install;
+mod integrity;
 mod platform_impl;
+mod progress;
+mod recovery;
+mod store;
+mod update_lock;
 
 /// Stateful object that handles the actual downloading of the Roblox client.
 ///
@@ -18,114 +47,634 @@ mod platform_impl;
 #[derive(Debug)]
 pub struct DownloadContext {
     pub client_lock: Option<ClientLock>,
+    /// Downloads client packages.
     client: Client,
-    /// Cached latest client version. Saved lazily.
+    /// Looks up versions and deploy history, and gives up on requests which stall.
+    lookup_client: Client,
+    endpoints: Endpoints,
+    /// Which client packages to download.
+    binary_type: BinaryType,
+    /// Which channel to follow the latest version of.
+    channel: Channel,
+    /// A version to install instead of the latest one, as a version GUID or dotted version.
+    pinned_version: Option<String>,
+    /// How many installed versions to keep, including the current one.
+    retained_versions: usize,
+    /// Where to install clients instead of this app's own `client/` directory, shared with other apps.
+    shared_store: Option<SharedStore>,
+    /// Runs the installed client, if it's a Windows client on another OS.
+    wine: Option<Wine>,
+    /// SHA-256 checksums that downloaded files must match, by file name.
+    checksums: HashMap<String, String>,
+    /// What each downloaded package can extract to.
+    extract_limits: ExtractLimits,
+    /// How requests which fail for transient reasons are retried.
+    retry: RetryPolicy,
+    /// How long requests can take before giving up.
+    timeouts: Timeouts,
+    /// On-disk cache of responses from Roblox, so we can start without waiting on (or even reaching) Roblox.
+    http_cache: HttpCache,
+    /// Cached version of the client that should be installed. Saved lazily.
     cached_client_version: Option<ClientVersionInfo>,
+    /// Set if the latest client version couldn't be found, and the installed client is being used instead.
+    version_check_failure: Option<VersionCheckFailure>,
+}
+
+/// Why the latest client version couldn't be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionCheckFailure {
+    /// Roblox couldn't be reached at all, most likely because there's no internet connection.
+    NoNetwork,
+    /// Roblox was reached, but didn't answer with a usable version.
+    ServerError,
+}
+
+impl VersionCheckFailure {
+    /// Works out whether `error` came from talking to Roblox. Anything else, like a pinned version that doesn't exist,
+    /// isn't a failed version check.
+    fn from_error(error: &anyhow::Error) -> Option<Self> {
+        // Classified the same way as a request the cache answered for, so the two always agree.
+        let no_network = error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+            .any(|error| Staleness::from_error(error) == Staleness::NoNetwork);
+        if no_network {
+            return Some(VersionCheckFailure::NoNetwork);
+        }
+
+        let server_error = error
+            .chain()
+            .any(|cause| cause.is::<deploy_history::Error>() || cause.is::<reqwest::Error>());
+        if server_error {
+            return Some(VersionCheckFailure::ServerError);
+        }
+
+        None
+    }
+
+    /// Works out whether any responses Roblox didn't answer with a fresh version were served from the cache instead.
+    /// Not being able to reach Roblox at all wins over a server error.
+    fn from_stale(stale: &[StaleResponse]) -> Option<Self> {
+        stale
+            .iter()
+            .map(|stale| VersionCheckFailure::from(stale.staleness))
+            .min_by_key(|failure| *failure != VersionCheckFailure::NoNetwork)
+    }
+}
+
+impl From<Staleness> for VersionCheckFailure {
+    fn from(staleness: Staleness) -> Self {
+        match staleness {
+            Staleness::NoNetwork => VersionCheckFailure::NoNetwork,
+            Staleness::ServerError => VersionCheckFailure::ServerError,
+        }
+    }
+}
+
+impl ToString for VersionCheckFailure {
+    fn to_string(&self) -> String {
+        let str = match self {
+            VersionCheckFailure::NoNetwork => "NoNetwork",
+            VersionCheckFailure::ServerError => "ServerError",
+        };
+
+        str.to_string()
+    }
 }
 
 impl DownloadContext {
-    pub fn new(root_dir: &Path) -> anyhow::Result<Self> {
-        // FIXME: Eating the error like this silences any parsing errors which could be helpful.
-        let client_lock = ClientLock::get(root_dir).ok();
+    /// Create a context which downloads the client that runs natively on this OS.
+    pub fn new(
+        root_dir: &Path,
+        endpoints: Endpoints,
+        update_lock: &UpdateLock,
+    ) -> anyhow::Result<Self> {
+        let binary_type =
+            platform_impl::native_binary_type().context("There is no Roblox client for this OS")?;
+
+        Self::with_binary_type(root_dir, endpoints, binary_type, update_lock)
+    }
+
+    /// Create a context which downloads the client packages for `binary_type`, whichever OS we're on. Loading
+    /// `client.lock` can migrate an older one and tidy up `client/`, so the `_update_lock` has to be held.
+    pub fn with_binary_type(
+        root_dir: &Path,
+        endpoints: Endpoints,
+        binary_type: BinaryType,
+        _update_lock: &UpdateLock,
+    ) -> anyhow::Result<Self> {
+        // Fail early if we can't download this client at all.
+        platform_impl::downloader_for(&binary_type)?;
+
+        // Leftovers have to go before the lock is loaded, or migrating a legacy lock would move them into the client.
+        // The rest of the clean up waits for `recover`, once the shared store is known.
+        recovery::clean_up_client_dir(root_dir)
+            .context("Failed to clean up after an interrupted client install")?;
+
+        // A lock we can't make sense of is kept for debugging, and the client is installed again from scratch.
+        let client_lock = ClientLock::load(root_dir, &binary_type).unwrap_or_else(|error| {
+            log::error!(
+                "Existing client.lock is unreadable, installing the client again: {error:?}"
+            );
+
+            match ClientLock::set_aside(root_dir) {
+                Ok(corrupt_path) => log::info!("Moved unreadable client.lock to {corrupt_path:?}"),
+                Err(error) => log::warn!("{error:?}"),
+            }
+
+            None
+        });
         log::debug!("Existing client.lock: {client_lock:?}");
 
-        let client = Client::builder()
-            .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/107.0.0.0 Safari/537.36")
-            .referer(false)
-            .build()?;
+        let timeouts = Timeouts::default();
+        let client = timeouts.download_client()?;
+        let lookup_client = timeouts.lookup_client()?;
+
+        let http_cache = HttpCache::new(root_dir.join("cache"));
 
         Ok(Self {
             client,
+            lookup_client,
+            endpoints,
+            binary_type,
+            channel: Channel::Live,
+            pinned_version: None,
+            retained_versions: DEFAULT_RETAINED_VERSIONS,
+            shared_store: None,
+            wine: None,
+            checksums: HashMap::new(),
+            extract_limits: ExtractLimits::default(),
+            retry: RetryPolicy::default(),
+            timeouts,
+            http_cache,
             client_lock,
             cached_client_version: None,
+            version_check_failure: None,
         })
     }
 
+    /// Require downloaded files to match a SHA-256 checksum, on top of any checksums Roblox publishes. Checksums are
+    /// keyed by file name, e.g. `version-d780cbcde4ab4f52-RobloxPlayer.zip`.
+    pub fn with_checksums(mut self, checksums: HashMap<String, String>) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Limit how much each downloaded package can extract to, in case it's a zip bomb.
+    pub fn with_extract_limits(mut self, extract_limits: ExtractLimits) -> Self {
+        self.extract_limits = extract_limits;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give up on requests to Roblox after `timeouts` instead of the defaults.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> anyhow::Result<Self> {
+        self.client = timeouts
+            .download_client()
+            .context("Failed to build HTTP client")?;
+        self.lookup_client = timeouts
+            .lookup_client()
+            .context("Failed to build HTTP client")?;
+        self.timeouts = timeouts;
+        Ok(self)
+    }
+
+    /// Follow the latest version on `channel` instead of `Channel::Live`.
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Install a specific version instead of the latest one, given as a version GUID (e.g. `version-d780cbcde4ab4f52`)
+    /// or a dotted version (e.g. `0.553.0.5530470`). The version is looked up in the deploy history of the channel.
+    pub fn with_pinned_version(mut self, version: Option<String>) -> Self {
+        self.pinned_version = version;
+        self
+    }
+
+    /// Keep up to `retained_versions` installed versions to fall back on, including the current one.
+    pub fn with_retained_versions(mut self, retained_versions: usize) -> Self {
+        self.retained_versions = retained_versions;
+        self
+    }
+
+    /// Install clients in a store shared with other apps, so each version is only downloaded once between them. With
+    /// no store, clients are installed in this app's own `client/` directory, which keeps the app fully portable.
+    pub fn with_shared_store(mut self, shared_store: Option<SharedStore>) -> Self {
+        self.shared_store = shared_store;
+        self
+    }
+
+    pub fn with_wine(mut self, wine: Option<Wine>) -> Self {
+        self.wine = wine;
+        self
+    }
+
+    /// Tidy up after a launcher which was closed part way through updating the client, in `client/` and in the shared
+    /// store if there is one. Call this once the context is built, before checking for updates. The `_update_lock`
+    /// keeps other launchers from updating meanwhile, whose installs in progress would otherwise look unfinished.
+    pub fn recover(&mut self, root_dir: &Path, _update_lock: &UpdateLock) -> anyhow::Result<()> {
+        self.client_lock = recovery::reconcile(root_dir, self.client_lock.take())
+            .context("Failed to reconcile client.lock with the installed clients")?;
+
+        if let Some(store) = self.store() {
+            recovery::reconcile_store(root_dir, &store, self.client_lock.as_ref())
+                .context("Failed to reconcile the shared store with client.lock")?;
+        }
+
+        log::debug!("Reconciled client.lock: {:?}", self.client_lock);
+
+        Ok(())
+    }
+
+    pub fn binary_type(&self) -> BinaryType {
+        self.binary_type
+    }
+
+    /// Why the latest client version couldn't be found, if `require_client_download` fell back to the installed client.
+    pub fn version_check_failure(&self) -> Option<VersionCheckFailure> {
+        self.version_check_failure
+    }
+
+    /// Where the current client version is installed, if there is one.
+    pub fn installed_client_dir(&self, root_dir: &Path) -> Option<PathBuf> {
+        self.client_lock
+            .as_ref()
+            .map(|client_lock| client_lock.current_dir(root_dir))
+    }
+
+    /// The current client version, and how to start it. Errors if there's no installed client.
+    pub fn installed_client(&self, root_dir: &Path) -> anyhow::Result<InstalledClient> {
+        let client_dir = self
+            .installed_client_dir(root_dir)
+            .context("There is no installed client")?;
+
+        let installed_client = self.downloader()?.installed_client(&client_dir);
+        let installed_client = match &self.wine {
+            Some(wine) => wine.wrap(installed_client),
+            None => installed_client,
+        };
+
+        Ok(installed_client)
+    }
+
     /// Start downloading the client! This mostly branches out to OS-specific download
     /// implementations because Roblox packages the client up different for Windows and Mac.
+    ///
+    /// The new client is built in a staging directory and only swapped in once it's complete, so a failed download
+    /// never touches the installed client. Older versions stay installed to fall back on, see `roll_back`. If the
+    /// version is already installed, or is in the shared store, it's made the current version without downloading
+    /// anything.
+    ///
+    /// Progress across every client file is reported through `progress`.
     pub async fn initiate_client_download(
         &mut self,
         root_dir: &Path,
+        progress: &ProgressTracker,
     ) -> anyhow::Result<()> {
-        let write_to = root_dir.join("client");
-
         let latest_version = self
             .get_latest_client_version()
             .await
             .context("Failed to get latest client version")?;
 
-        let download_paths = Downloader::get_file_download_paths(&self.client, &latest_version)
+        if let Some(installed) = self.installed_instead_of_broken(&latest_version) {
+            log::warn!(
+                "Client {latest_version} was rolled back from, staying on client {}",
+                installed.version
+            );
+            return Ok(());
+        }
+
+        let version_guid = latest_version.version_guid.to_owned();
+        let store = self.store();
+        let store_dir = self.store_dir(root_dir);
+        let version_dir = install::version_dir(&store_dir, &version_guid);
+
+        let binary_type = self.binary_type;
+        let client_lock = self
+            .client_lock
+            .as_mut()
+            .filter(|lock| lock.store == store && lock.binary_type == binary_type);
+        if let Some(client_lock) = client_lock {
+            if client_lock.installed(&version_guid).is_some() && version_dir.exists() {
+                log::info!("Client {latest_version} is already installed, switching to it");
+
+                client_lock.current = version_guid;
+                client_lock.broken_versions.clear();
+                client_lock
+                    .write_lock_to_path(root_dir)
+                    .context("Failed to write new ClientLock to path")?;
+
+                return Ok(());
+            }
+        }
+
+        // Versions are only moved into the store once they're complete, so there's no need to check it over.
+        let installed = if store.is_some() && version_dir.exists() {
+            log::info!("Client {latest_version} is already in the shared store");
+
+            let files = integrity::read_client_hashes(&version_dir)
+                .context("Failed to read hashes of client in shared store")?;
+            InstalledVersion::new(latest_version, Vec::new(), files)
+        } else {
+            self.download_client(root_dir, &store_dir, latest_version, progress)
+                .await?
+        };
+
+        if let Some(store) = &store {
+            store::add_reference(store, &version_guid, root_dir)
+                .context("Failed to reference client in shared store")?;
+        }
+
+        // A lock for another store or binary type is replaced outright, so everything in it is removed.
+        let (mut new_lock, replaced_lock) = match self.client_lock.clone() {
+            Some(mut client_lock)
+                if client_lock.store == store && client_lock.binary_type == binary_type =>
+            {
+                client_lock.install(installed);
+                (client_lock, None)
+            }
+            replaced_lock => (
+                ClientLock::new(binary_type, installed, store),
+                replaced_lock,
+            ),
+        };
+
+        let pruned = new_lock.prune(self.retained_versions);
+
+        new_lock
+            .write_lock_to_path(root_dir)
+            .context("Failed to write new ClientLock to path")?;
+
+        let mut removed = pruned
+            .into_iter()
+            .map(|installed| (new_lock.store.clone(), installed.version))
+            .collect::<Vec<_>>();
+        if let Some(replaced_lock) = replaced_lock {
+            removed.extend(
+                replaced_lock
+                    .versions
+                    .into_iter()
+                    .map(|installed| (replaced_lock.store.clone(), installed.version)),
+            );
+        }
+
+        self.client_lock = Some(new_lock);
+
+        // The lock no longer points at these, so failing to delete them only wastes space.
+        for (store, version) in removed {
+            log::info!("Removing old client {version}");
+
+            if let Err(error) = remove_installed(root_dir, store.as_deref(), &version) {
+                log::warn!("Failed to remove old client {version}: {error:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Download `version` and move it into `store_dir`, once it's complete.
+    async fn download_client(
+        &self,
+        root_dir: &Path,
+        store_dir: &Path,
+        latest_version: ClientVersionInfo,
+        progress: &ProgressTracker,
+    ) -> anyhow::Result<InstalledVersion> {
+        let downloader = self.downloader()?;
+
+        let mut downloads = downloader
+            .get_file_download_paths(
+                &self.lookup_client,
+                &self.retry,
+                &self.endpoints,
+                &latest_version,
+            )
             .await
             .context("Failed to get client download paths")?;
 
-        log::debug!("Got download paths:\n{}", download_paths.join(",\n"));
+        for download in &mut downloads {
+            let file_name = download.url.rsplit('/').next().unwrap_or_default();
+            if let Some(sha256) = self.checksums.get(file_name) {
+                download.checksums.push(Checksum::Sha256(sha256.to_owned()));
+            }
+
+            // Count every size we know up front, so the total doesn't jump as each file starts downloading.
+            if let Some(size) = download.size {
+                progress.set_size(&download.url, size);
+            }
+        }
 
-        Downloader::download_files_and_write_to_path(&self.client, download_paths, &write_to)
+        let source_urls = downloads
+            .iter()
+            .map(|download| download.url.to_owned())
+            .collect::<Vec<_>>();
+        log::debug!("Got download paths:\n{}", source_urls.join(",\n"));
+
+        // Partial downloads are kept between launches, so an interrupted download of the same version can pick up
+        // where it left off.
+        let downloads_dir = root_dir.join(install::DOWNLOADS_DIR);
+        remove_stale_downloads(&downloads_dir, &latest_version.version_guid);
+
+        let download_dir = downloads_dir.join(&latest_version.version_guid);
+        fs::create_dir_all(&download_dir).context(format!(
+            "Failed to create download directory {download_dir:?}"
+        ))?;
+
+        let write_to = install::prepare_staging(store_dir, &latest_version.version_guid)?;
+
+        downloader
+            .download_files_and_write_to_path(
+                &self.client,
+                &self.retry,
+                &self.endpoints,
+                downloads,
+                &download_dir,
+                &write_to,
+                &self.extract_limits,
+                self.timeouts.read_timeout(),
+                progress,
+            )
             .await
             .context("Failed to download files or write to path")?;
 
-        self.update_client_lock(root_dir)
-            .await
-            .context("Failed to update client.lock")?;
+        progress.flush();
+
+        let files =
+            integrity::record_client_hashes(&write_to).context("Failed to record client hashes")?;
+
+        install::swap_in_staged(store_dir, &latest_version.version_guid)
+            .context("Failed to install new client")?;
+
+        // Finished packages are kept until now, so an interrupted install doesn't download them again.
+        if let Err(error) = fs::remove_dir_all(&download_dir) {
+            log::warn!("Failed to clean up download directory {download_dir:?}: {error}");
+        }
+
+        Ok(InstalledVersion::new(latest_version, source_urls, files))
+    }
+
+    /// Go back to the most recently installed version before the current one, e.g. because the current one turned
+    /// out to be broken. The current version is deleted, unless another app is using it from the shared store.
+    pub fn roll_back(&mut self, root_dir: &Path) -> anyhow::Result<()> {
+        let Some(client_lock) = &mut self.client_lock else {
+            bail!("There is no installed client to roll back from");
+        };
+
+        let Some(previous) = client_lock.previous().cloned() else {
+            bail!("There is no previous client to roll back to");
+        };
+
+        let current = client_lock.current.to_owned();
+        let Some(broken) = client_lock.remove(&current) else {
+            bail!("The current client {current} isn't installed");
+        };
+        client_lock.current = previous.version.version_guid.to_owned();
+        // Otherwise the next launch would find it missing and install it all over again.
+        client_lock.broken_versions.push(current);
+
+        client_lock
+            .write_lock_to_path(root_dir)
+            .context("Failed to write new ClientLock to path")?;
+
+        log::warn!("Rolled back to client {}", previous.version);
+
+        let broken = broken.version;
+        if let Err(error) = remove_installed(root_dir, client_lock.store.as_deref(), &broken) {
+            log::warn!("Failed to remove broken client {broken}: {error:?}");
+        }
 
         Ok(())
     }
 
+    /// The installed client to keep using if `version` was rolled back from, since it'd only be broken again.
+    fn installed_instead_of_broken(
+        &self,
+        version: &ClientVersionInfo,
+    ) -> Option<&InstalledVersion> {
+        let client_lock = self.client_lock.as_ref()?;
+        if !client_lock.is_broken(&version.version_guid) {
+            return None;
+        }
+
+        client_lock.current()
+    }
+
     /// Works out if we need to download a new version of the client.
     /// A new download could be required when:
     ///  1. Could not find an existing client downloaded.
     ///  2. Could not find or parse an existing `client.lock` file.
-    ///  3. The client version defined in `client.lock` predates the latest client version.
+    ///  3. The current client version in `client.lock` isn't the pinned version, or the latest version if there's no
+    ///     pinned version.
+    ///  4. The installed client isn't in the store we're installing to, e.g. because the app opted out of the shared
+    ///     store.
+    ///  5. The installed client is for another binary type.
     ///
-    /// Errors out if we failed to get the latest client version.
+    /// A version which was rolled back from is never required, the installed client is kept until another is
+    /// published.
+    ///
+    /// If we failed to get the latest client version because Roblox couldn't be reached or answered with an error, the
+    /// installed client is used as it is and the reason is kept in `version_check_failure`. Errors out if there's no
+    /// installed client to fall back on.
     pub async fn require_client_download(&mut self) -> anyhow::Result<bool> {
-        let latest_version = self
-            .get_latest_client_version()
-            .await
-            .context("Failed to get latest client version")?;
+        // Only responses served stale for this check count.
+        self.http_cache.take_stale();
 
-        if let Some(client_lock) = &self.client_lock {
-            // Roblox version strings don't follow semver rules, which makes comparing
-            // versions a bit of a pain. This is probably the most robust way to do it.
-            // Will also catch cases where we're somehow ahead of the latest client
-            // version (maybe we downloaded a test branch?).
-            let lock_version = &client_lock.version;
-            if lock_version.version != latest_version.version
-                || lock_version.patch != latest_version.patch
-                || lock_version.change_list != latest_version.change_list
-            {
-                return Ok(true);
+        let latest_version = match self.get_latest_client_version().await {
+            Ok(latest_version) => latest_version,
+            Err(error) => {
+                let installed = self.client_lock.as_ref().and_then(ClientLock::current);
+                let failure = VersionCheckFailure::from_error(&error);
+
+                let (Some(installed), Some(failure)) = (installed, failure) else {
+                    return Err(error.context("Failed to get latest client version"));
+                };
+
+                log::warn!(
+                    "Failed to get latest client version ({failure:?}), using installed client {}: {error:?}",
+                    installed.version
+                );
+
+                self.version_check_failure = Some(failure);
+                return Ok(false);
             }
-        } else {
-            // No client.lock file, require download. This also includes parse errors.
-            return Ok(true);
+        };
+
+        // The cache answers for Roblox when it can't, so the version found may be out of date.
+        if let Some(failure) = VersionCheckFailure::from_stale(&self.http_cache.take_stale()) {
+            self.version_check_failure = Some(failure);
+
+            if let Some(installed) = self.client_lock.as_ref().and_then(ClientLock::current) {
+                log::warn!(
+                    "Couldn't check for a newer client ({failure:?}), using installed client {}",
+                    installed.version
+                );
+                return Ok(false);
+            }
+
+            log::warn!(
+                "Couldn't check for a newer client ({failure:?}), installing the last known client {latest_version}"
+            );
         }
 
-        Ok(false)
-    }
+        if let Some(installed) = self.installed_instead_of_broken(&latest_version) {
+            log::warn!(
+                "Latest client {latest_version} was rolled back from, using installed client {} until another is published",
+                installed.version
+            );
+            return Ok(false);
+        }
 
-    /// Update the client lock with the currently installed (latest) version of the client.
-    /// This should only be called after client installation has completed.
-    pub async fn update_client_lock(&mut self, root_dir: &Path) -> anyhow::Result<()> {
-        let latest_version = self
-            .get_latest_client_version()
-            .await
-            .context("Failed to get latest client version")?;
+        if let Some(client_lock) = &self.client_lock {
+            if client_lock.store != self.store() {
+                log::info!("Installed client is in another store, it needs to be installed again");
+                return Ok(true);
+            }
 
-        let new_lock = ClientLock {
-            version: latest_version,
-        };
+            if client_lock.binary_type != self.binary_type {
+                log::info!(
+                    "Installed client is a {}, it needs to be replaced with a {}",
+                    client_lock.binary_type.to_string(),
+                    self.binary_type.to_string()
+                );
+                return Ok(true);
+            }
+        }
 
-        new_lock
-            .write_lock_to_path(&new_lock, root_dir)
-            .context("Failed to write new ClientLock to path")?;
+        if let Some(installed) = self.client_lock.as_ref().and_then(ClientLock::current) {
+            let lock_version = &installed.version;
+            if lock_version.version_guid == latest_version.version_guid {
+                log::info!("Installed client {lock_version} is up to date");
+                return Ok(false);
+            }
 
-        self.client_lock = Some(new_lock);
+            match lock_version.cmp(&latest_version) {
+                Ordering::Less => {
+                    log::info!("Installed client {lock_version} is behind {latest_version}");
+                }
+                // We're somehow ahead of the version we want (maybe we downloaded a test branch, or the version was
+                // pinned?). Go back to the version we want.
+                Ordering::Greater => {
+                    log::warn!("Installed client {lock_version} is ahead of {latest_version}");
+                }
+                Ordering::Equal => {
+                    log::info!(
+                        "Installed client {lock_version} is a different build to {latest_version}"
+                    );
+                }
+            }
+        }
 
-        log::debug!("Wrote new ClientLock to path");
-        Ok(())
+        // Either the installed version is out of date, or there's no client.lock file. This also includes a client.lock
+        // which was unreadable, and has been set aside.
+        Ok(true)
     }
 
+    /// The version that should be installed, which is the pinned version if there is one.
     async fn get_latest_client_version(&mut self) -> anyhow::Result<ClientVersionInfo> {
         if let Some(version_info) = &self.cached_client_version {
             log::debug!("Hit cached client version info");
@@ -133,10 +682,24 @@ impl DownloadContext {
         } else {
             log::debug!("Missed cached client version info");
 
-            // FIXME: Parse the correct `BinaryType`.
-            let version_info = Downloader::get_latest_client_version(&self.client)
-                .await
-                .context("Failed to get latest client version")?;
+            let version_info = if let Some(pinned_version) = &self.pinned_version {
+                self.get_pinned_client_version(pinned_version)
+                    .await
+                    .context(format!(
+                        "Failed to find pinned client version {pinned_version}"
+                    ))?
+            } else {
+                self.downloader()?
+                    .get_latest_client_version(
+                        &self.lookup_client,
+                        &self.retry,
+                        &self.endpoints,
+                        Some(&self.http_cache),
+                        &self.channel,
+                    )
+                    .await
+                    .context("Failed to get latest client version")?
+            };
 
             // FIXME PERF: Don't clone here.
             self.cached_client_version = Some(version_info.clone());
@@ -144,4 +707,116 @@ impl DownloadContext {
             Ok(version_info)
         }
     }
+
+    /// Look up `pinned_version` in the installed versions, falling back to the deploy history of the channel.
+    async fn get_pinned_client_version(
+        &self,
+        pinned_version: &str,
+    ) -> anyhow::Result<ClientVersionInfo> {
+        let matches = |version_guid: &str, version: &str| {
+            version_guid == pinned_version || version == pinned_version
+        };
+
+        let installed = self.client_lock.iter().flat_map(|lock| &lock.versions);
+        for installed in installed {
+            let version = &installed.version;
+            if matches(&version.version_guid, &version.to_string()) {
+                return Ok(version.clone());
+            }
+        }
+
+        let history = get_deploy_logs_for_channel(
+            &self.lookup_client,
+            &self.endpoints,
+            Some(&self.http_cache),
+            &self.retry,
+            &self.channel,
+            Some(&self.binary_type),
+        )
+        .await
+        .context("Failed to get deploy history")?;
+
+        for log in history.logs.iter().rev() {
+            let version = log
+                .file_version
+                .map(|(major_rev, version, patch, change_list)| {
+                    format!("{major_rev}.{version}.{patch}.{change_list}")
+                });
+
+            if !matches(&log.version_guid, version.as_deref().unwrap_or_default()) {
+                continue;
+            }
+
+            let Some(version) = version else {
+                bail!(
+                    "The deploy history doesn't record which version {} is",
+                    log.version_guid
+                );
+            };
+
+            return Ok(ClientVersionInfo::new(
+                self.channel.to_owned(),
+                version,
+                log.version_guid.to_owned(),
+            )?);
+        }
+
+        bail!(
+            "{} {pinned_version} was never deployed to {}",
+            self.binary_type.to_string(),
+            self.channel.to_string()
+        )
+    }
+
+    /// The shared store directory to install versions of this binary type in, if there is one.
+    fn store(&self) -> Option<PathBuf> {
+        self.shared_store
+            .as_ref()
+            .map(|shared_store| shared_store.versions_dir(&self.binary_type))
+    }
+
+    fn store_dir(&self, root_dir: &Path) -> PathBuf {
+        self.store()
+            .unwrap_or_else(|| root_dir.join(install::CLIENT_DIR))
+    }
+
+    fn downloader(&self) -> anyhow::Result<&'static dyn ClientDownloader> {
+        platform_impl::downloader_for(&self.binary_type)
+    }
+}
+
+/// Remove `version` from this app, which was installed in the shared store directory `store` if there is one.
+/// Versions in the shared store are only deleted once no other app uses them.
+fn remove_installed(
+    root_dir: &Path,
+    store: Option<&Path>,
+    version: &ClientVersionInfo,
+) -> anyhow::Result<()> {
+    match store {
+        Some(store) => store::release(store, &version.version_guid, root_dir),
+        None => install::remove_version(&root_dir.join(install::CLIENT_DIR), &version.version_guid),
+    }
+}
+
+/// How many installed versions are kept by default, including the current one.
+pub const DEFAULT_RETAINED_VERSIONS: usize = 3;
+
+/// Partial downloads of any other version will never be resumed, so there's no point keeping them around.
+fn remove_stale_downloads(downloads_dir: &Path, version_guid: &str) {
+    let Ok(entries) = fs::read_dir(downloads_dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        if entry.file_name() == version_guid {
+            continue;
+        }
+
+        let path = entry.path();
+        log::debug!("Removing stale download {path:?}");
+
+        if let Err(error) = fs::remove_dir_all(&path) {
+            log::warn!("Failed to remove stale download {path:?}: {error}");
+        }
+    }
 }
@@ -0,0 +1,595 @@
+//! Extracting client packages, without trusting anything the archive says about itself.
+//!
+//! Packages are checked against their checksums before they're extracted when Roblox publishes them, but Mac packages
+//! have none unless the manifest sets them, so an archive could come from anyone who can tamper with the CDN
+//! response. Nothing in an archive can be written outside the directory it's extracted into, and limits on its size
+//! stop a small archive from filling the disk.
+
+use std::{
+    fs,
+    io::{self, Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+/// File type bits of a Unix mode.
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Entries smaller than this aren't held to `max_compression_ratio`, small files of repeated bytes legitimately
+/// compress very well.
+const MIN_SIZE_FOR_RATIO: u64 = 1024 * 1024;
+
+/// The longest symlink target read from an archive. Targets are stored as the link's contents.
+const MAX_SYMLINK_TARGET: u64 = 4096;
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("Failed to read archive")]
+    Archive(#[from] ZipError),
+
+    #[error("Failed to write {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Archive has more than {limit} entries")]
+    TooManyEntries { limit: usize },
+
+    #[error("Archive is larger than {limit} bytes uncompressed")]
+    TooLarge { limit: u64 },
+
+    #[error("{name:?} is compressed more than {limit} times, it may be a zip bomb")]
+    SuspiciousCompression { name: String, limit: u64 },
+
+    #[error("{name:?} is larger than the archive says it is")]
+    SizeMismatch { name: String },
+
+    #[error("{name:?} would be extracted outside of the target directory")]
+    UnsafePath { name: String },
+
+    #[error("{name:?} would be extracted through a symlink")]
+    ThroughSymlink { name: String },
+
+    #[error("{name:?} links to {target:?}, outside of the target directory")]
+    UnsafeSymlink { name: String, target: String },
+}
+
+/// Limits on what a single archive can extract to, so a malicious archive can't fill the disk.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExtractLimits {
+    /// Most bytes an archive can extract to in total.
+    pub max_size: u64,
+    /// Most entries (files, directories and symlinks) an archive can have.
+    pub max_entries: usize,
+    /// Most times larger than its compressed size any file over 1 MiB can be.
+    pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 4 * 1024 * 1024 * 1024,
+            max_entries: 100_000,
+            max_compression_ratio: 1_000,
+        }
+    }
+}
+
+/// Extract every entry of `archive` into `write_to`.
+///
+/// Permissions are taken from the archive, except for the setuid, setgid and sticky bits, and the owner can always
+/// read and write what's extracted. Symlinks (e.g. the frameworks in a Mac `.app` bundle) are recreated as long as
+/// they link to somewhere inside `write_to`.
+pub fn extract_archive<T: Read + Seek>(
+    archive: &mut ZipArchive<T>,
+    write_to: &Path,
+    limits: &ExtractLimits,
+) -> Result<(), ExtractError> {
+    if archive.len() > limits.max_entries {
+        return Err(ExtractError::TooManyEntries {
+            limit: limits.max_entries,
+        });
+    }
+
+    let mut extracted_size = 0;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_owned();
+
+        let Some(relative_path) = file.enclosed_name().map(Path::to_owned) else {
+            return Err(ExtractError::UnsafePath { name });
+        };
+        check_not_through_symlink(write_to, &relative_path, &name)?;
+        let outpath = write_to.join(&relative_path);
+
+        let mode = file.unix_mode();
+        if mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+            let target = read_symlink_target(&mut file, &outpath, &name)?;
+            check_symlink_target(&relative_path, &target, &name)?;
+
+            log::trace!("File {i} linked at {outpath:?} to {target:?}");
+            create_parent(&outpath)?;
+            create_symlink(&target, &outpath)?;
+            continue;
+        }
+
+        if file.is_dir() {
+            log::trace!("File {i} extracted to {outpath:?}");
+            fs::create_dir_all(&outpath).map_err(|source| ExtractError::Io {
+                path: outpath.to_owned(),
+                source,
+            })?;
+        } else {
+            let size = file.size();
+            log::trace!("File {i} extracted to {outpath:?} ({size} bytes)");
+
+            if size >= MIN_SIZE_FOR_RATIO
+                && size / file.compressed_size().max(1) > limits.max_compression_ratio
+            {
+                return Err(ExtractError::SuspiciousCompression {
+                    name,
+                    limit: limits.max_compression_ratio,
+                });
+            }
+
+            extracted_size += size;
+            if extracted_size > limits.max_size {
+                return Err(ExtractError::TooLarge {
+                    limit: limits.max_size,
+                });
+            }
+
+            create_parent(&outpath)?;
+            write_file(&mut file, &outpath, size, &name)?;
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            let permissions = fs::Permissions::from_mode(sanitize_mode(mode, file.is_dir()));
+            fs::set_permissions(&outpath, permissions).map_err(|source| ExtractError::Io {
+                path: outpath.to_owned(),
+                source,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy no more than the `size` the archive declares for an entry, in case it lies.
+fn write_file(
+    file: &mut impl Read,
+    outpath: &Path,
+    size: u64,
+    name: &str,
+) -> Result<(), ExtractError> {
+    let io_error = |source| ExtractError::Io {
+        path: outpath.to_owned(),
+        source,
+    };
+
+    let mut outfile = fs::File::create(outpath).map_err(io_error)?;
+    let mut limited = file.take(size);
+    io::copy(&mut limited, &mut outfile).map_err(io_error)?;
+
+    let file = limited.into_inner();
+    if file.read(&mut [0]).map_err(io_error)? != 0 {
+        return Err(ExtractError::SizeMismatch {
+            name: name.to_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+fn create_parent(outpath: &Path) -> Result<(), ExtractError> {
+    let Some(parent) = outpath.parent() else {
+        return Ok(());
+    };
+
+    fs::create_dir_all(parent).map_err(|source| ExtractError::Io {
+        path: parent.to_owned(),
+        source,
+    })
+}
+
+/// An entry written through a symlink extracted earlier could land anywhere the symlink points to, and an earlier
+/// symlink at the entry's own path would be followed when it's written.
+fn check_not_through_symlink(
+    write_to: &Path,
+    relative_path: &Path,
+    name: &str,
+) -> Result<(), ExtractError> {
+    let mut path = write_to.to_owned();
+    for component in relative_path.components() {
+        path.push(component);
+
+        if path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_symlink())
+        {
+            return Err(ExtractError::ThroughSymlink {
+                name: name.to_owned(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn read_symlink_target(
+    file: &mut impl Read,
+    outpath: &Path,
+    name: &str,
+) -> Result<String, ExtractError> {
+    let mut target = Vec::new();
+    file.take(MAX_SYMLINK_TARGET)
+        .read_to_end(&mut target)
+        .map_err(|source| ExtractError::Io {
+            path: outpath.to_owned(),
+            source,
+        })?;
+
+    String::from_utf8(target).map_err(|error| ExtractError::UnsafeSymlink {
+        name: name.to_owned(),
+        target: String::from_utf8_lossy(error.as_bytes()).into_owned(),
+    })
+}
+
+/// A symlink target must be relative, and can only go up (`..`) before it goes down, and no further up than the
+/// directory being extracted into. Going up anywhere else could go up out of a directory another symlink leads to,
+/// which isn't where it looks like it goes.
+fn check_symlink_target(
+    relative_path: &Path,
+    target: &str,
+    name: &str,
+) -> Result<(), ExtractError> {
+    let unsafe_symlink = || ExtractError::UnsafeSymlink {
+        name: name.to_owned(),
+        target: target.to_owned(),
+    };
+
+    // The depth of the directory the symlink is in.
+    let mut depth = relative_path.components().count().saturating_sub(1);
+    let mut gone_down = false;
+
+    if target.is_empty() {
+        return Err(unsafe_symlink());
+    }
+
+    for component in Path::new(target).components() {
+        match component {
+            Component::ParentDir if !gone_down && depth > 0 => depth -= 1,
+            Component::Normal(_) => gone_down = true,
+            Component::CurDir => {}
+            _ => return Err(unsafe_symlink()),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, outpath: &Path) -> Result<(), ExtractError> {
+    std::os::unix::fs::symlink(target, outpath).map_err(|source| ExtractError::Io {
+        path: outpath.to_owned(),
+        source,
+    })
+}
+
+/// Only the Mac client has symlinks, and it's only ever run on Mac. Other OSes only extract it in tests.
+#[cfg(not(unix))]
+fn create_symlink(target: &str, outpath: &Path) -> Result<(), ExtractError> {
+    log::debug!("Skipping symlink {outpath:?} to {target:?}, symlinks are only extracted on Unix");
+    Ok(())
+}
+
+/// Only the permission bits are kept, and the owner can always read and write (and search directories), so the
+/// client can be replaced and deleted later.
+#[cfg(unix)]
+fn sanitize_mode(mode: u32, is_dir: bool) -> u32 {
+    let mode = (mode & 0o777) | 0o600;
+    if is_dir {
+        mode | 0o100
+    } else {
+        mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Write},
+        path::Path,
+    };
+
+    use tempfile::TempDir;
+    use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+    use crate::downloader::platform_impl::extract::{extract_archive, ExtractError, ExtractLimits};
+
+    /// An entry of a test archive.
+    enum Entry<'a> {
+        File(&'a str, &'a [u8]),
+        Dir(&'a str),
+        Symlink(&'a str, &'a str),
+    }
+
+    fn build_archive(entries: &[Entry]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for entry in entries {
+            match entry {
+                Entry::File(name, contents) => {
+                    writer.start_file(*name, options).unwrap();
+                    writer.write_all(contents).unwrap();
+                }
+                Entry::Dir(name) => writer.add_directory(*name, options).unwrap(),
+                Entry::Symlink(name, target) => {
+                    writer.add_symlink(*name, *target, options).unwrap()
+                }
+            }
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Overwrite a little-endian `u32` at `offset` in the central directory header of the first entry, for what
+    /// `ZipWriter` won't write.
+    fn patch_central_header(archive: &mut [u8], offset: usize, value: u32) {
+        let header = archive
+            .windows(4)
+            .position(|window| window == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        archive[header + offset..header + offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn extract_bytes(
+        archive: Vec<u8>,
+        limits: &ExtractLimits,
+    ) -> (TempDir, Result<(), ExtractError>) {
+        let write_to = TempDir::new().unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        let result = extract_archive(&mut archive, write_to.path(), limits);
+        (write_to, result)
+    }
+
+    fn extract(entries: &[Entry], limits: &ExtractLimits) -> (TempDir, Result<(), ExtractError>) {
+        extract_bytes(build_archive(entries), limits)
+    }
+
+    #[test]
+    fn extracts_files_and_directories() {
+        let (write_to, result) = extract(
+            &[
+                Entry::Dir("RobloxPlayer.app/"),
+                Entry::File("RobloxPlayer.app/Contents/Info.plist", b"<plist/>"),
+            ],
+            &ExtractLimits::default(),
+        );
+
+        result.unwrap();
+        assert_eq!(
+            fs::read(write_to.path().join("RobloxPlayer.app/Contents/Info.plist")).unwrap(),
+            b"<plist/>"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_frameworks_inside_bundle() {
+        let (write_to, result) = extract(
+            &[
+                Entry::File(
+                    "RobloxPlayer.app/Contents/Frameworks/Ogre.framework/Versions/A/Ogre",
+                    b"ogre",
+                ),
+                Entry::Symlink(
+                    "RobloxPlayer.app/Contents/Frameworks/Ogre.framework/Versions/Current",
+                    "A",
+                ),
+                Entry::Symlink(
+                    "RobloxPlayer.app/Contents/Frameworks/Ogre.framework/Ogre",
+                    "Versions/Current/Ogre",
+                ),
+                Entry::Symlink(
+                    "RobloxPlayer.app/Contents/Resources/Ogre",
+                    "../Frameworks/Ogre.framework/Ogre",
+                ),
+            ],
+            &ExtractLimits::default(),
+        );
+
+        result.unwrap();
+        let framework = write_to
+            .path()
+            .join("RobloxPlayer.app/Contents/Frameworks/Ogre.framework");
+        assert!(framework
+            .join("Ogre")
+            .symlink_metadata()
+            .unwrap()
+            .is_symlink());
+        assert_eq!(
+            fs::read_link(framework.join("Versions/Current")).unwrap(),
+            Path::new("A")
+        );
+        assert_eq!(
+            fs::read(
+                write_to
+                    .path()
+                    .join("RobloxPlayer.app/Contents/Resources/Ogre")
+            )
+            .unwrap(),
+            b"ogre"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sanitizes_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let extracted_mode = |mode: u32| {
+            let mut archive = build_archive(&[Entry::File("RobloxPlayer", b"player")]);
+            // The mode is kept in the top half of the external attributes.
+            patch_central_header(&mut archive, 38, (0o100000 | mode) << 16);
+
+            let (write_to, result) = extract_bytes(archive, &ExtractLimits::default());
+            result.unwrap();
+
+            fs::metadata(write_to.path().join("RobloxPlayer"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o7777
+        };
+
+        assert_eq!(extracted_mode(0o755), 0o755);
+        assert_eq!(extracted_mode(0o4755), 0o755);
+        assert_eq!(extracted_mode(0o444), 0o644);
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        for name in ["../evil", "RobloxPlayer.app/../../evil", "/tmp/evil"] {
+            let (write_to, result) =
+                extract(&[Entry::File(name, b"evil")], &ExtractLimits::default());
+
+            assert!(
+                matches!(result, Err(ExtractError::UnsafePath { .. })),
+                "{name}: {result:?}"
+            );
+            assert!(!write_to.path().parent().unwrap().join("evil").exists());
+        }
+    }
+
+    #[test]
+    fn rejects_symlinks_outside_target() {
+        let targets = ["/etc/passwd", "../../evil", "Contents/../../../evil", ""];
+
+        for target in targets {
+            let (_write_to, result) = extract(
+                &[Entry::Symlink("RobloxPlayer.app/Link", target)],
+                &ExtractLimits::default(),
+            );
+
+            assert!(
+                matches!(result, Err(ExtractError::UnsafeSymlink { .. })),
+                "{target}: {result:?}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_writing_through_symlinks() {
+        // The link is safe by itself, but would take the next entry outside of the target once followed.
+        let (write_to, result) = extract(
+            &[
+                Entry::Dir("a/b/"),
+                Entry::Symlink("a/b/up", "../.."),
+                Entry::File("a/b/up/evil", b"evil"),
+            ],
+            &ExtractLimits::default(),
+        );
+
+        assert!(matches!(result, Err(ExtractError::ThroughSymlink { .. })));
+        assert!(!write_to.path().join("evil").exists());
+
+        // An entry at the same path as a symlink would be written wherever it points.
+        let (_write_to, result) = extract(
+            &[
+                Entry::Symlink("Info.plist", "RobloxPlayer"),
+                Entry::File("Info.plist", b"evil"),
+            ],
+            &ExtractLimits::default(),
+        );
+
+        assert!(matches!(result, Err(ExtractError::ThroughSymlink { .. })));
+    }
+
+    #[test]
+    fn limits_entry_count() {
+        let limits = ExtractLimits {
+            max_entries: 2,
+            ..Default::default()
+        };
+        let (write_to, result) = extract(
+            &[
+                Entry::File("a", b"a"),
+                Entry::File("b", b"b"),
+                Entry::File("c", b"c"),
+            ],
+            &limits,
+        );
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::TooManyEntries { limit: 2 })
+        ));
+        assert_eq!(fs::read_dir(write_to.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn limits_total_size() {
+        let limits = ExtractLimits {
+            max_size: 1_000,
+            ..Default::default()
+        };
+        let (_write_to, result) = extract(
+            &[Entry::File("a", &[1; 600]), Entry::File("b", &[2; 600])],
+            &limits,
+        );
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::TooLarge { limit: 1_000 })
+        ));
+    }
+
+    #[test]
+    fn rejects_zip_bombs() {
+        let limits = ExtractLimits {
+            max_compression_ratio: 100,
+            ..Default::default()
+        };
+        // 4 MiB of zeros compresses to a few KiB.
+        let zeros = vec![0; 4 * 1024 * 1024];
+        let (write_to, result) = extract(&[Entry::File("bomb", &zeros)], &limits);
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::SuspiciousCompression { .. })
+        ));
+        assert!(!write_to.path().join("bomb").exists());
+    }
+
+    #[test]
+    fn rejects_understated_sizes() {
+        let mut archive = build_archive(&[Entry::File("data.bin", &[7; 100])]);
+        // Claim the file is 10 bytes uncompressed.
+        patch_central_header(&mut archive, 24, 10);
+
+        let (write_to, result) = extract_bytes(archive, &ExtractLimits::default());
+
+        assert!(matches!(result, Err(ExtractError::SizeMismatch { .. })));
+        assert!(
+            fs::metadata(write_to.path().join("data.bin"))
+                .unwrap()
+                .len()
+                <= 10
+        );
+    }
+}
@@ -1,36 +1,150 @@
-use std::path::Path;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
 
+use anyhow::bail;
 use async_trait::async_trait;
-use deploy_history::client_version_info::ClientVersionInfo;
+use deploy_history::{
+    cache::HttpCache,
+    client_version_info::ClientVersionInfo,
+    domain::{BinaryType, Channel},
+    endpoints::Endpoints,
+    retry::RetryPolicy,
+};
 use reqwest::Client;
 
-#[cfg(target_os = "windows")]
-pub use windows::WindowsDownloader as Downloader;
+use super::{integrity::Checksum, progress::ProgressTracker};
 
-#[cfg(target_os = "macos")]
-pub use macos::MacDownloader as Downloader;
+pub use extract::{ExtractError, ExtractLimits};
+pub use macos::MacDownloader;
+pub use windows::WindowsDownloader;
 
-#[cfg(target_os = "windows")]
-mod windows;
-
-#[cfg(target_os = "macos")]
+// Client packages are plain ZIPs, so every downloader builds everywhere. This lets their download paths be tested on
+// any OS.
 mod macos;
+mod windows;
 
+mod extract;
 mod util;
 
+/// A file which makes up part of the client.
+#[derive(Debug, Clone)]
+pub struct FileDownload {
+    pub url: String,
+    /// The downloaded file must match all of these before it's used.
+    pub checksums: Vec<Checksum>,
+    /// Size of the file, if it's known before downloading it.
+    pub size: Option<u64>,
+}
+
+/// A client installed in a directory, and everything needed to start its player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledClient {
+    /// The player executable.
+    pub executable: PathBuf,
+    /// The directory the player is started in.
+    pub working_dir: PathBuf,
+    /// A program which runs `executable` for us, if it can't be run directly (e.g. Wine).
+    pub runner: Option<PathBuf>,
+    /// Set on top of the launcher's own environment.
+    pub env: Vec<(String, OsString)>,
+}
+
+impl InstalledClient {
+    /// A player which runs directly, from the client directory.
+    pub fn new(client_dir: &Path, executable: &str) -> Self {
+        Self {
+            executable: client_dir.join(executable),
+            working_dir: client_dir.to_owned(),
+            runner: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// A command which starts the player. Arguments for the player can be added to it.
+    pub fn command(&self) -> Command {
+        let mut command = match &self.runner {
+            Some(runner) => {
+                let mut command = Command::new(runner);
+                command.arg(&self.executable);
+                command
+            }
+            None => Command::new(&self.executable),
+        };
+
+        command
+            .current_dir(&self.working_dir)
+            .envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        command
+    }
+}
+
 /// Structure for OS-specific client downloaders.
 #[async_trait]
-pub trait ClientDownloader {
-    async fn get_latest_client_version(client: &Client) -> anyhow::Result<ClientVersionInfo>;
+pub trait ClientDownloader: Send + Sync {
+    async fn get_latest_client_version(
+        &self,
+        client: &Client,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
+        cache: Option<&HttpCache>,
+        channel: &Channel,
+    ) -> anyhow::Result<ClientVersionInfo>;
 
     async fn get_file_download_paths(
+        &self,
         client: &Client,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
         version_info: &ClientVersionInfo,
-    ) -> anyhow::Result<Vec<String>>;
+    ) -> anyhow::Result<Vec<FileDownload>>;
 
+    /// Download the client into `write_to`, a fresh directory which is only swapped in for the installed client once
+    /// the download has succeeded. Partial and finished downloads are kept in `download_dir`, so they can be resumed by
+    /// calling this again with the same `download_dir`. Bytes received are counted towards `progress`, and each package is
+    /// held to `limits` when it's extracted. A download which receives nothing for `read_timeout` is retried.
+    #[allow(clippy::too_many_arguments)]
     async fn download_files_and_write_to_path(
+        &self,
         client: &Client,
-        download_paths: Vec<String>,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
+        downloads: Vec<FileDownload>,
+        download_dir: &Path,
         write_to: &Path,
+        limits: &ExtractLimits,
+        read_timeout: Duration,
+        progress: &ProgressTracker,
     ) -> anyhow::Result<()>;
+
+    /// The client installed in `client_dir`. This doesn't check the client is actually there.
+    fn installed_client(&self, client_dir: &Path) -> InstalledClient;
+}
+
+/// The player binary type to install on this OS. Roblox doesn't make a Linux client, so Linux gets the Windows client
+/// to run through Wine.
+pub fn native_binary_type() -> Option<BinaryType> {
+    if cfg!(any(target_os = "windows", target_os = "linux")) {
+        Some(BinaryType::WindowsPlayer)
+    } else if cfg!(target_os = "macos") {
+        Some(BinaryType::MacPlayer)
+    } else {
+        None
+    }
+}
+
+/// Get the downloader for the packages of `binary_type`.
+pub fn downloader_for(binary_type: &BinaryType) -> anyhow::Result<&'static dyn ClientDownloader> {
+    match binary_type {
+        BinaryType::MacPlayer => Ok(&MacDownloader),
+        BinaryType::WindowsPlayer => Ok(&WindowsDownloader),
+        binary_type => bail!(
+            "Downloading {} clients is not supported",
+            binary_type.to_string()
+        ),
+    }
 }
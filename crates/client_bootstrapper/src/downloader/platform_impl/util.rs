@@ -1,85 +1,162 @@
 //! Collection of shared utilities between OS-specific downloader implementations.
 
 use std::{
-    fs,
-    io::{Read, Seek},
-    path::{Path, PathBuf},
-    str::FromStr,
+    fs, future::Future, io::SeekFrom, path::Path, str::FromStr, sync::Mutex, time::Duration,
 };
 
 use anyhow::{bail, Context};
+use deploy_history::retry::{retry_for_error, Retry, RetryPolicy};
 use futures::future;
 use reqwest::{
     header::{HeaderValue, CONTENT_LENGTH, RANGE},
     Client, StatusCode,
 };
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+    time,
+};
 use zip::ZipArchive;
 
-/// Download a client files and write them to path asynchronously.
+use super::{
+    extract::{extract_archive, ExtractLimits},
+    FileDownload,
+};
+use crate::downloader::progress::ProgressTracker;
+
+/// Download a client file and extract it into `extract_to` asynchronously.
+///
+/// The file is downloaded in parallel chunks, and each chunk is streamed straight to its offset in a preallocated
+/// file in `download_dir` as it arrives. Memory use stays the same no matter how big the file is.
+///
+/// The whole file is checked against the download's checksums before it's extracted, and extraction is held to
+/// `limits`.
+///
+/// Finished chunks are recorded next to the partial file, so if the download is interrupted (the launcher is closed,
+/// the network drops, etc.) the next download of the same URL into the same `download_dir` only fetches the missing
+/// chunks. The finished file is kept in `download_dir` after it's extracted, so if the launcher is closed before the
+/// whole client is installed, the next attempt only extracts it again without going back to the CDN. It's up to the
+/// caller to delete `download_dir` once the client is installed.
+///
+/// Bytes are counted towards `progress` as they're written. Requests which fail for transient reasons are retried
+/// according to `retry`, including chunks which fail part way through or receive nothing for `read_timeout`.
+#[allow(clippy::too_many_arguments)]
 pub async fn download_file(
     client: &Client,
-    url: &str,
-    write_to: &Path,
+    retry: &RetryPolicy,
+    download: &FileDownload,
+    download_dir: &Path,
+    extract_to: &Path,
+    limits: &ExtractLimits,
+    read_timeout: Duration,
     target_concurrent_downloads: u32,
-) -> anyhow::Result<PathBuf> {
+    progress: &ProgressTracker,
+) -> anyhow::Result<()> {
+    let url = download.url.as_str();
     let hash = sha256::digest(url);
     log::debug!("Downloading {url} ({hash})");
 
-    // Get the content length so we can download the file in parallel chunks
-    let response = client
-        .head(url)
-        .send()
-        .await
-        .context(format!("Failed to make HEAD reqwest to {url}"))?;
-
-    let content_length = response
-        .headers()
-        .get(CONTENT_LENGTH)
-        .context("HEAD response does not include content length")?
-        .to_str()
-        .context("Failed to convert content length to string slice")?;
+    let path = download_dir.join(format!("{hash}.zip"));
+    let state_path = download_dir.join(format!("{hash}.toml"));
 
-    let content_length =
-        u64::from_str(content_length).context("Failed to convert string slice to u64")?;
+    let content_length = match DownloadState::finished(&state_path, &path, url) {
+        Some(content_length) => {
+            log::info!("{url} was already downloaded, extracting it again");
+            content_length
+        }
+        None => get_content_length(client, retry, url).await?,
+    };
 
     log::debug!("Content length for {url}: {content_length}");
+    progress.set_size(url, content_length);
+
+    let state = match DownloadState::resume(&state_path, &path, url, content_length) {
+        Some(state) => state,
+        None => {
+            // Preallocate the ZIP so every chunk can be written straight to its place in the file.
+            let file = File::create(&path)
+                .await
+                .context(format!("Failed to create {path:?}"))?;
+            file.set_len(content_length)
+                .await
+                .context(format!("Failed to preallocate {path:?}"))?;
+
+            let state = DownloadState {
+                url: url.to_owned(),
+                content_length,
+                completed: Vec::new(),
+            };
+            state
+                .save(&state_path)
+                .context("Failed to save download state")?;
+
+            state
+        }
+    };
 
     // Start downloading chunks
     log::debug!("Downloading file at {url}");
 
-    let buffer_size = content_length.div_floor(target_concurrent_downloads as u64);
+    let buffer_size = content_length
+        .div_floor(target_concurrent_downloads as u64)
+        .max(1);
     let range_iter = PartialRangeIter::new(0, content_length - 1, buffer_size)
         .context("Failed to make range iter")?;
 
-    log::debug!("Download chunks for {url}: {}", range_iter.clone().count());
+    let missing_ranges: Vec<_> = range_iter
+        .filter(|range| !state.completed.contains(range))
+        .collect();
+
+    if state.completed.is_empty() {
+        log::debug!("Download chunks for {url}: {}", missing_ranges.len());
+    } else {
+        progress.resumed(url, state.completed.iter().map(ByteRange::len).sum());
+
+        log::info!(
+            "Resuming download of {url}, {} chunks left",
+            missing_ranges.len()
+        );
+    }
+
+    let state = Mutex::new(state);
 
     // Make a list of all async download jobs and await them all together
     let mut download_tasks = Vec::new();
-    for range in range_iter {
-        download_tasks.push(download_partial_chunk(client, url, range));
+    for range in missing_ranges {
+        let path = &path;
+        let state = &state;
+        let state_path = &state_path;
+
+        download_tasks.push(async move {
+            download_partial_chunk(client, retry, url, range, path, read_timeout, progress).await?;
+
+            state
+                .lock()
+                .unwrap()
+                .complete(range, state_path)
+                .context("Failed to save download state")
+        });
     }
 
-    let downloaded_chunks = future::try_join_all(download_tasks)
+    future::try_join_all(download_tasks)
         .await
-        .context("Failed to download {url}")?;
-
-    log::debug!("Downloaded file at {url}");
+        .context(format!("Failed to download {url}"))?;
 
-    // Join all downloaded chunks into one byte array and write to path
-    let mut file_bytes = Vec::new();
-    for mut chunk in downloaded_chunks {
-        file_bytes.append(&mut chunk);
-    }
+    log::debug!("Downloaded ZIP {url} ({hash}) to path. Now verifying.");
 
-    log::debug!("Writing ZIP {url} ({hash}) to path");
+    for checksum in &download.checksums {
+        if let Err(error) = checksum.verify(&path) {
+            // Start from scratch next time, we can't tell which chunk is bad.
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(&state_path);
 
-    // Write the ZIP file to path before extracting it.
-    // TODO: Work out how to skip this redundant step. Writing to disk wastes time if we can just
-    //  immediately extract the ZIP.
-    let path = write_to.join(format!("{hash}.zip"));
-    fs::write(&path, file_bytes).context("Failed to write RobloxPlayer ZIP to path")?;
+            return Err(error).context(format!("{url} is corrupt"));
+        }
+    }
 
-    log::debug!("Wrote ZIP {url} ({hash}) to path. Now extracting.");
+    log::debug!("Verified ZIP {url} ({hash}). Now extracting.");
 
     // Extract the ZIP.
     // TODO: Make ZIP extraction async for Windows because there's a lot of files to extract.
@@ -88,88 +165,257 @@ pub async fn download_file(
         ZipArchive::new(file).context(format!("Failed to create archive for path {path:?}"))?;
 
     log::debug!("Parsed ZIP archive for {url}");
-    extract_archive(&mut archive, write_to).context("Failed to extract archive")?;
+    extract_archive(&mut archive, extract_to, limits)
+        .context(format!("Failed to extract {url}"))?;
 
-    Ok(path)
+    Ok(())
 }
 
-/// Download a partial file chunk from the CDN in parallel to speed up download
-async fn download_partial_chunk(
+/// Ask the CDN how big `url` is, so it can be downloaded in parallel chunks.
+async fn get_content_length(
     client: &Client,
+    retry: &RetryPolicy,
     url: &str,
-    range: HeaderValue,
-) -> anyhow::Result<Vec<u8>> {
-    log::trace!("Range {range:?} ({url})");
-
-    let response = client
-        .get(url)
-        .header(RANGE, &range)
-        .send()
+) -> anyhow::Result<u64> {
+    let response = retry
+        .send(|| client.head(url))
         .await
-        .context("Request for range {range:?} at {url} failed")?;
+        .context(format!("Failed to make HEAD reqwest to {url}"))?
+        .error_for_status()
+        .context(format!("Failed to get {url}"))?;
 
-    let status = response.status();
-    if !(status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT) {
-        bail!("Got unexpected response from CDN ({url} {range:?}): {status}");
+    let content_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .context("HEAD response does not include content length")?
+        .to_str()
+        .context("Failed to convert content length to string slice")?;
+
+    let content_length =
+        u64::from_str(content_length).context("Failed to convert string slice to u64")?;
+
+    if content_length == 0 {
+        bail!("{url} is empty");
     }
 
-    let bytes = response
-        .bytes()
-        .await
-        .context("Failed to get bytes from CDN response {url} ({range:?})")?
-        .to_vec();
+    Ok(content_length)
+}
 
-    Ok(bytes)
+/// Which chunks of a partial download have been written to disk.
+#[derive(Debug, Deserialize, Serialize)]
+struct DownloadState {
+    url: String,
+    content_length: u64,
+    completed: Vec<ByteRange>,
 }
 
-/// Modified from https://github.com/zip-rs/zip/blob/5737927dbbd15a8b648c315f2f8e2a39cdc1a430/examples/extract.rs
-fn extract_archive<T: Read + Seek>(
-    archive: &mut ZipArchive<T>,
-    write_to: &Path,
-) -> anyhow::Result<()> {
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
-        let outpath = write_to.join(match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        });
+impl DownloadState {
+    /// Load the state of an earlier download, if it's for the same file and the partial file is still there.
+    fn resume(state_path: &Path, path: &Path, url: &str, content_length: u64) -> Option<Self> {
+        let state = fs::read_to_string(state_path).ok()?;
+        let state = toml::from_str::<DownloadState>(&state).ok()?;
 
-        if (*file.name()).ends_with('/') {
-            log::trace!("File {i} extracted to \"{outpath:?}\"");
-            fs::create_dir_all(&outpath)
-                .context(format!("Failed to create directory {outpath:?}"))?;
-        } else {
-            log::trace!(
-                "File {i} extracted to \"{outpath:?}\" ({} bytes)",
-                file.size()
-            );
+        let partial_length = fs::metadata(path).ok()?.len();
+
+        (state.url == url
+            && state.content_length == content_length
+            && partial_length == content_length)
+            .then_some(state)
+    }
+
+    /// The length of an earlier download of `url` which was finished, if there is one.
+    fn finished(state_path: &Path, path: &Path, url: &str) -> Option<u64> {
+        let state = fs::read_to_string(state_path).ok()?;
+        let state = toml::from_str::<DownloadState>(&state).ok()?;
+        let state = Self::resume(state_path, path, url, state.content_length)?;
+
+        let completed = state.completed.iter().map(ByteRange::len).sum::<u64>();
+        (completed == state.content_length).then_some(state.content_length)
+    }
+
+    fn complete(&mut self, range: ByteRange, state_path: &Path) -> anyhow::Result<()> {
+        self.completed.push(range);
+        self.save(state_path)
+    }
+
+    /// Written to a temporary file first, so a crash mid-write can't leave a state file claiming chunks we don't
+    /// have.
+    fn save(&self, state_path: &Path) -> anyhow::Result<()> {
+        let state = toml::to_string(self).context("Failed to convert download state to string")?;
+
+        let temp_path = state_path.with_extension("toml.tmp");
+        fs::write(&temp_path, state).context(format!("Failed to write {temp_path:?}"))?;
+        fs::rename(&temp_path, state_path).context(format!("Failed to write {state_path:?}"))?;
 
-            if let Some(path) = outpath.parent() {
-                if !path.exists() {
-                    fs::create_dir_all(&path)
-                        .context(format!("Failed to create directory {path:?}"))?;
-                }
+        Ok(())
+    }
+}
+
+/// Download a partial file chunk from the CDN in parallel to speed up download, writing it into its range of the
+/// file at `path` as it arrives.
+///
+/// If the connection fails or stalls part way through, or the CDN rate limits or errors, the whole chunk is downloaded
+/// again, and whatever the failed attempt counted towards `progress` is taken back. Each attempt makes a single
+/// request.
+async fn download_partial_chunk(
+    client: &Client,
+    retry: &RetryPolicy,
+    url: &str,
+    range: ByteRange,
+    path: &Path,
+    read_timeout: Duration,
+    progress: &ProgressTracker,
+) -> anyhow::Result<()> {
+    retry
+        .run(|| async move {
+            let mut written = 0;
+            let chunk = ChunkRequest {
+                url,
+                range,
+                read_timeout,
+            };
+
+            write_partial_chunk(client, retry, &chunk, path, progress, &mut written)
+                .await
+                .map_err(|error| {
+                    progress.discarded(written);
+
+                    let decision = retry_for_chunk_error(&error);
+                    (error, decision)
+                })
+        })
+        .await
+}
+
+/// Only failures talking to the CDN are worth retrying, not failures writing to disk or a CDN which misbehaves.
+fn retry_for_chunk_error(error: &anyhow::Error) -> Retry {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(error) = cause.downcast_ref::<ChunkStatusError>() {
+                Some(error.retry)
+            } else if cause.is::<ChunkStalledError>() {
+                // The stall has been waited out already, so there's no point backing off as well.
+                Some(Retry::After(Duration::ZERO))
+            } else {
+                cause.downcast_ref::<reqwest::Error>().map(retry_for_error)
             }
+        })
+        .unwrap_or(Retry::Never)
+}
 
-            let mut outfile = fs::File::create(&outpath)
-                .context(format!("Failed to create file at {outpath:?}"))?;
+/// The CDN answered a request for a chunk with an error status. Rate limiting and server errors are worth retrying.
+#[derive(Debug, Error)]
+#[error("Got unexpected response from CDN ({url} {header:?}): {status}")]
+struct ChunkStatusError {
+    url: String,
+    header: HeaderValue,
+    status: StatusCode,
+    retry: Retry,
+}
 
-            std::io::copy(&mut file, &mut outfile).context(format!(
-                "Failed to copy file contents into writer for {outpath:?}"
-            ))?;
-        }
+/// The CDN sent nothing for a chunk for too long, without closing the connection.
+#[derive(Debug, Error)]
+#[error("CDN sent nothing for {timeout:?} ({url} {header:?})")]
+struct ChunkStalledError {
+    url: String,
+    header: HeaderValue,
+    timeout: Duration,
+}
 
-        // Get and Set permissions
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+/// A chunk of the file at `url` to download.
+#[derive(Debug, Clone, Copy)]
+struct ChunkRequest<'a> {
+    url: &'a str,
+    range: ByteRange,
+    /// How long to wait for the response, and then for each part of the body.
+    read_timeout: Duration,
+}
 
-            if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)).unwrap();
+impl ChunkRequest<'_> {
+    /// Wait for `future`, unless the CDN stalls.
+    async fn read<T>(&self, future: impl Future<Output = T>) -> anyhow::Result<T> {
+        time::timeout(self.read_timeout, future).await.map_err(|_| {
+            ChunkStalledError {
+                url: self.url.to_owned(),
+                header: self.range.header(),
+                timeout: self.read_timeout,
             }
+            .into()
+        })
+    }
+}
+
+async fn write_partial_chunk(
+    client: &Client,
+    retry: &RetryPolicy,
+    chunk: &ChunkRequest<'_>,
+    path: &Path,
+    progress: &ProgressTracker,
+    written: &mut u64,
+) -> anyhow::Result<()> {
+    let ChunkRequest { url, range, .. } = *chunk;
+    let header = range.header();
+    log::trace!("Range {header:?} ({url})");
+
+    // Sent once per attempt, the caller retries the whole chunk whether the request or the body fails.
+    let mut response = chunk
+        .read(client.get(url).header(RANGE, &header).send())
+        .await?
+        .context(format!("Request for range {header:?} at {url} failed"))?;
+
+    let status = response.status();
+    if !(status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT) {
+        return Err(ChunkStatusError {
+            url: url.to_owned(),
+            header,
+            status,
+            retry: retry.retry_for_response(&response),
+        }
+        .into());
+    }
+
+    // Each chunk gets its own handle, so they each have their own position in the file.
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .context(format!("Failed to open {path:?}"))?;
+    file.seek(SeekFrom::Start(range.start))
+        .await
+        .context(format!("Failed to seek to {} in {path:?}", range.start))?;
+
+    while let Some(bytes) = chunk.read(response.chunk()).await?.context(format!(
+        "Failed to get bytes from CDN response {url} ({header:?})"
+    ))? {
+        // A CDN which ignores the range would send the whole file, which would overrun the next chunk.
+        if *written + bytes.len() as u64 > range.len() {
+            bail!(
+                "CDN sent more than the {} bytes requested ({url} {header:?})",
+                range.len()
+            );
         }
+
+        file.write_all(&bytes)
+            .await
+            .context(format!("Failed to write {header:?} to {path:?}"))?;
+
+        *written += bytes.len() as u64;
+        progress.received(url, bytes.len() as u64);
+    }
+
+    if *written != range.len() {
+        bail!(
+            "CDN sent {written} bytes, expected {} ({url} {header:?})",
+            range.len()
+        );
     }
 
+    // Make sure the chunk is really on disk before it's recorded as complete.
+    file.sync_data()
+        .await
+        .context(format!("Failed to sync {path:?}"))?;
+
     Ok(())
 }
 
@@ -196,17 +442,479 @@ impl PartialRangeIter {
 }
 
 impl Iterator for PartialRangeIter {
-    type Item = HeaderValue;
+    type Item = ByteRange;
     fn next(&mut self) -> Option<Self::Item> {
         if self.start > self.end {
             None
         } else {
             let prev_start = self.start;
             self.start += std::cmp::min(self.buffer_size, self.end - self.start + 1);
-            Some(
-                HeaderValue::from_str(&format!("bytes={}-{}", prev_start, self.start - 1))
-                    .expect("string provided by format!"),
+            Some(ByteRange {
+                start: prev_start,
+                end: self.start - 1,
+            })
+        }
+    }
+}
+
+/// An inclusive range of bytes in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ByteRange {
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn header(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("bytes={}-{}", self.start, self.end))
+            .expect("string provided by format!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use deploy_history::retry::{FakeClock, RetryPolicy};
+    use mock_roblox::{zip_package, MockRoblox};
+    use reqwest::{header::RANGE, Client, StatusCode};
+    use tempfile::TempDir;
+
+    use crate::downloader::{
+        integrity::Checksum,
+        platform_impl::{
+            util::{download_file, ByteRange, DownloadState, PartialRangeIter},
+            ExtractLimits, FileDownload,
+        },
+        progress::{DownloadProgress, ProgressTracker},
+    };
+    use crate::http::Timeouts;
+
+    fn get_package() -> (Vec<u8>, Vec<u8>) {
+        let contents: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        let package = zip_package(&[("data.bin", &contents)]);
+        (contents, package)
+    }
+
+    fn get_download(url: &str, checksums: Vec<Checksum>) -> FileDownload {
+        FileDownload {
+            url: url.to_owned(),
+            checksums,
+            size: None,
+        }
+    }
+
+    /// Retries without waiting.
+    fn get_retry() -> RetryPolicy {
+        RetryPolicy::default().with_clock(Arc::new(FakeClock::new(SystemTime::UNIX_EPOCH)))
+    }
+
+    fn requested_ranges(mock: &MockRoblox) -> Vec<String> {
+        mock.requests()
+            .iter()
+            .filter_map(|request| request.headers.get(RANGE))
+            .map(|range| range.to_str().unwrap().to_owned())
+            .collect()
+    }
+
+    fn get_progress() -> (ProgressTracker, Arc<Mutex<Vec<DownloadProgress>>>) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let tracker_reports = Arc::clone(&reports);
+        let progress = ProgressTracker::new(move |progress| {
+            tracker_reports.lock().unwrap().push(progress);
+        });
+
+        (progress, reports)
+    }
+
+    /// Leave a partial download behind, as if the launcher was closed once `completed` chunks were downloaded.
+    fn write_partial_download(
+        download_dir: &Path,
+        url: &str,
+        package: &[u8],
+        content_length: u64,
+        completed: &[ByteRange],
+    ) {
+        let mut partial = vec![0; package.len()];
+        for range in completed {
+            let (start, end) = (range.start as usize, range.end as usize);
+            partial[start..=end].copy_from_slice(&package[start..=end]);
+        }
+
+        let hash = sha256::digest(url);
+        fs::write(download_dir.join(format!("{hash}.zip")), partial).unwrap();
+
+        DownloadState {
+            url: url.to_owned(),
+            content_length,
+            completed: completed.to_vec(),
+        }
+        .save(&download_dir.join(format!("{hash}.toml")))
+        .unwrap();
+    }
+
+    #[test]
+    fn splits_ranges() {
+        let ranges: Vec<_> = PartialRangeIter::new(0, 9, 4).unwrap().collect();
+
+        assert_eq!(
+            ranges,
+            [
+                ByteRange { start: 0, end: 3 },
+                ByteRange { start: 4, end: 7 },
+                ByteRange { start: 8, end: 9 },
+            ]
+        );
+        assert_eq!(ranges[2].len(), 2);
+        assert_eq!(ranges[2].header(), "bytes=8-9");
+    }
+
+    #[tokio::test]
+    async fn downloads_chunks_into_place() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        let package_len = package.len() as u64;
+        mock.add_file("/package.zip", package);
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        let url = format!("{}/package.zip", mock.url());
+        let (progress, reports) = get_progress();
+        download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            7,
+            &progress,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fs::read(extract_to.path().join("data.bin")).unwrap(),
+            contents
+        );
+        assert!(requested_ranges(&mock).len() >= 7);
+
+        progress.flush();
+        let last = reports.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.file, "package.zip");
+        assert_eq!(last.bytes_received, package_len);
+        assert_eq!(last.total_bytes, package_len);
+
+        // The finished download is kept, in case the rest of the client is interrupted.
+        assert_eq!(fs::read_dir(download_dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn extracts_finished_download_again() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        mock.add_file("/package.zip", package);
+        let url = format!("{}/package.zip", mock.url());
+        let download_dir = TempDir::new().unwrap();
+        let mut requests = Vec::new();
+
+        for _ in 0..2 {
+            let extract_to = TempDir::new().unwrap();
+            download_file(
+                &Client::new(),
+                &get_retry(),
+                &get_download(&url, Vec::new()),
+                download_dir.path(),
+                extract_to.path(),
+                &ExtractLimits::default(),
+                Timeouts::default().read_timeout(),
+                4,
+                &ProgressTracker::ignored(),
             )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                fs::read(extract_to.path().join("data.bin")).unwrap(),
+                contents
+            );
+            requests.push(mock.requests().len());
         }
+
+        // The second time, the file was extracted without going back to the CDN at all.
+        assert_eq!(requests[0], requests[1]);
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_download() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        mock.add_file("/package.zip", package.clone());
+        let url = format!("{}/package.zip", mock.url());
+
+        let content_length = package.len() as u64;
+        let ranges: Vec<_> = PartialRangeIter::new(0, content_length - 1, content_length / 4)
+            .unwrap()
+            .collect();
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        write_partial_download(
+            download_dir.path(),
+            &url,
+            &package,
+            content_length,
+            &ranges[..2],
+        );
+
+        let (progress, reports) = get_progress();
+        download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            4,
+            &progress,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fs::read(extract_to.path().join("data.bin")).unwrap(),
+            contents
+        );
+
+        let requested = requested_ranges(&mock);
+        assert_eq!(requested.len(), ranges.len() - 2);
+        for range in &ranges[..2] {
+            assert!(!requested.contains(&range.header().to_str().unwrap().to_owned()));
+        }
+
+        // Resumed chunks still count towards progress.
+        progress.flush();
+        let last = reports.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(last.bytes_received, content_length);
+    }
+
+    #[tokio::test]
+    async fn restarts_mismatched_download() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        mock.add_file("/package.zip", package.clone());
+        let url = format!("{}/package.zip", mock.url());
+
+        // The file on the CDN has changed size since the partial download was started.
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        write_partial_download(
+            download_dir.path(),
+            &url,
+            &package,
+            package.len() as u64 + 1,
+            &[ByteRange { start: 0, end: 99 }],
+        );
+
+        download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            4,
+            &ProgressTracker::ignored(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fs::read(extract_to.path().join("data.bin")).unwrap(),
+            contents
+        );
+
+        // Every chunk was downloaded again, including the one the stale state claimed.
+        let content_length = package.len() as u64;
+        let chunks = PartialRangeIter::new(0, content_length - 1, content_length / 4)
+            .unwrap()
+            .count();
+        assert_eq!(requested_ranges(&mock).len(), chunks);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_failures() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        mock.add_file("/package.zip", package);
+        mock.fail_requests("/package.zip", StatusCode::SERVICE_UNAVAILABLE, 3);
+        let url = format!("{}/package.zip", mock.url());
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            4,
+            &ProgressTracker::ignored(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fs::read(extract_to.path().join("data.bin")).unwrap(),
+            contents
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_persistent_failures() {
+        let mock = MockRoblox::start();
+        let (_, package) = get_package();
+        mock.add_file("/package.zip", package);
+        mock.fail_requests("/package.zip", StatusCode::SERVICE_UNAVAILABLE, usize::MAX);
+        let url = format!("{}/package.zip", mock.url());
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        let result = download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            4,
+            &ProgressTracker::ignored(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            mock.requests().len() as u32,
+            RetryPolicy::default().max_attempts
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_stalled_chunks() {
+        let mock = MockRoblox::start();
+        let (contents, package) = get_package();
+        mock.add_file("/package.zip", package);
+        mock.stall_range_requests("/package.zip", 1);
+        let url = format!("{}/package.zip", mock.url());
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            download_file(
+                &Client::new(),
+                &get_retry(),
+                &get_download(&url, Vec::new()),
+                download_dir.path(),
+                extract_to.path(),
+                &ExtractLimits::default(),
+                Duration::from_millis(200),
+                4,
+                &ProgressTracker::ignored(),
+            ),
+        )
+        .await
+        .expect("the stalled chunk should time out")
+        .unwrap();
+
+        assert_eq!(
+            fs::read(extract_to.path().join("data.bin")).unwrap(),
+            contents
+        );
+
+        // Only the stalled chunk was requested again.
+        let ranges = requested_ranges(&mock);
+        let distinct = ranges.iter().collect::<HashSet<_>>().len();
+        assert_eq!(ranges.len(), distinct + 1);
+        assert_eq!(
+            ranges.iter().filter(|range| **range == ranges[0]).count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_chunks_once_per_attempt() {
+        let mock = MockRoblox::start();
+        let (_, package) = get_package();
+        mock.add_file("/package.zip", package);
+        mock.fail_range_requests("/package.zip", StatusCode::SERVICE_UNAVAILABLE, usize::MAX);
+        let url = format!("{}/package.zip", mock.url());
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        let result = download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, Vec::new()),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            1,
+            &ProgressTracker::ignored(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            requested_ranges(&mock).len() as u32,
+            RetryPolicy::default().max_attempts
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_corrupt_download() {
+        let mock = MockRoblox::start();
+        let (_, package) = get_package();
+        mock.add_file("/package.zip", package);
+        let url = format!("{}/package.zip", mock.url());
+
+        let download_dir = TempDir::new().unwrap();
+        let extract_to = TempDir::new().unwrap();
+        let checksums = vec![Checksum::Sha256("0".repeat(64))];
+        let result = download_file(
+            &Client::new(),
+            &get_retry(),
+            &get_download(&url, checksums),
+            download_dir.path(),
+            extract_to.path(),
+            &ExtractLimits::default(),
+            Timeouts::default().read_timeout(),
+            4,
+            &ProgressTracker::ignored(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(!extract_to.path().join("data.bin").exists());
+
+        // The bad download isn't resumed.
+        assert_eq!(fs::read_dir(download_dir.path()).unwrap().count(), 0);
     }
 }
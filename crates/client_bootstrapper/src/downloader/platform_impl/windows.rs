@@ -1,32 +1,529 @@
-use anyhow::Context;
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{bail, Context};
 use async_trait::async_trait;
 use deploy_history::{
+    cache::HttpCache,
     client_version_info::ClientVersionInfo,
     domain::{BinaryType, Channel},
+    endpoints::Endpoints,
+    retry::RetryPolicy,
 };
+use futures::{stream, StreamExt, TryStreamExt};
 use reqwest::Client;
 
-use super::ClientDownloader;
+use super::{util::download_file, ClientDownloader, ExtractLimits, FileDownload, InstalledClient};
+use crate::downloader::{integrity::Checksum, progress::ProgressTracker};
+
+/// Lists every package that makes up a version of the Windows client.
+const PACKAGE_MANIFEST_FILE: &str = "rbxPkgManifest.txt";
+
+/// Windows is split into many packages, so download a few at once rather than splitting each one up as much as Mac.
+const TARGET_CONCURRENT_PACKAGES: usize = 4;
+const TARGET_CONCURRENT_DOWNLOADS: u32 = 4;
+
+const PLAYER_EXECUTABLE: &str = "RobloxPlayerBeta.exe";
 
 pub struct WindowsDownloader;
 
+/// A package listed in `rbxPkgManifest.txt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    /// MD5 checksum of the package.
+    pub checksum: String,
+    pub packed_size: u64,
+    pub size: u64,
+}
+
 #[async_trait]
 impl ClientDownloader for WindowsDownloader {
-    async fn get_latest_client_version(client: &Client) -> anyhow::Result<ClientVersionInfo> {
-        let version_info =
-            ClientVersionInfo::get(client, &Channel::Live, &BinaryType::WindowsPlayer)
-                .await
-                .context("Failed to get latest version info")?;
+    async fn get_latest_client_version(
+        &self,
+        client: &Client,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
+        cache: Option<&HttpCache>,
+        channel: &Channel,
+    ) -> anyhow::Result<ClientVersionInfo> {
+        let version_info = ClientVersionInfo::get(
+            client,
+            endpoints,
+            cache,
+            retry,
+            channel,
+            &BinaryType::WindowsPlayer,
+        )
+        .await
+        .context("Failed to get latest version info")?;
 
         Ok(version_info)
     }
 
-    /// In the case of Mac, we already know the download paths beforehand because it's only two files.
-    /// Still, we need the version info to generate the paths.
+    /// Windows packages change between versions, so the list of packages is read from the version's package
+    /// manifest.
     async fn get_file_download_paths(
-        _client: &Client,
-        _version_info: &ClientVersionInfo,
-    ) -> anyhow::Result<Vec<String>> {
-        todo!("Windows support is a WIP.");
+        &self,
+        client: &Client,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
+        version_info: &ClientVersionInfo,
+    ) -> anyhow::Result<Vec<FileDownload>> {
+        let base_url = format!(
+            "{}/{}",
+            version_info.channel.base_url(endpoints),
+            version_info.version_guid
+        );
+
+        let manifest_url = format!("{base_url}-{PACKAGE_MANIFEST_FILE}");
+        let response = retry
+            .send(|| client.get(&manifest_url))
+            .await
+            .context(format!("Failed to request {manifest_url}"))?
+            .error_for_status()
+            .context(format!("Failed to get {manifest_url}"))?;
+
+        let manifest = response
+            .text()
+            .await
+            .context("Failed to read package manifest")?;
+
+        let packages =
+            parse_package_manifest(&manifest).context("Failed to parse package manifest")?;
+
+        // The manifest also lists loose files such as `RobloxPlayerLauncher.exe`, which the client doesn't need.
+        let downloads = packages
+            .into_iter()
+            .filter(|package| package.name.ends_with(".zip"))
+            .map(|package| FileDownload {
+                url: format!("{base_url}-{}", package.name),
+                checksums: vec![Checksum::Md5(package.checksum)],
+                size: Some(package.packed_size),
+            })
+            .collect();
+
+        Ok(downloads)
+    }
+
+    async fn download_files_and_write_to_path(
+        &self,
+        client: &Client,
+        retry: &RetryPolicy,
+        endpoints: &Endpoints,
+        downloads: Vec<FileDownload>,
+        download_dir: &Path,
+        write_to: &Path,
+        limits: &ExtractLimits,
+        read_timeout: Duration,
+        progress: &ProgressTracker,
+    ) -> anyhow::Result<()> {
+        fs::create_dir_all(write_to)
+            .context(format!("Failed to create client directory: {write_to:?}"))?;
+
+        stream::iter(downloads)
+            .map(|download| {
+                download_package(
+                    client,
+                    retry,
+                    download,
+                    download_dir,
+                    write_to,
+                    limits,
+                    read_timeout,
+                    progress,
+                )
+            })
+            .buffer_unordered(TARGET_CONCURRENT_PACKAGES)
+            .try_collect::<Vec<_>>()
+            .await
+            .context("Failed to download one or more client packages")?;
+
+        fs::write(
+            write_to.join("AppSettings.xml"),
+            app_settings(&endpoints.www),
+        )
+        .context("Failed to write AppSettings.xml")?;
+
+        log::info!("Got Roblox Player: {write_to:?}");
+
+        Ok(())
+    }
+
+    fn installed_client(&self, client_dir: &Path) -> InstalledClient {
+        InstalledClient::new(client_dir, PLAYER_EXECUTABLE)
+    }
+}
+
+/// The client won't start without this, it tells the client where to find its content and which site it belongs to.
+fn app_settings(base_url: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Settings>
+	<ContentFolder>content</ContentFolder>
+	<BaseUrl>{base_url}</BaseUrl>
+</Settings>
+"#
+    )
+}
+
+/// Download a package and extract it into its place in the client directory.
+#[allow(clippy::too_many_arguments)]
+async fn download_package(
+    client: &Client,
+    retry: &RetryPolicy,
+    download: FileDownload,
+    download_dir: &Path,
+    write_to: &Path,
+    limits: &ExtractLimits,
+    read_timeout: Duration,
+    progress: &ProgressTracker,
+) -> anyhow::Result<()> {
+    let name = package_name(&download.url)
+        .context(format!("Failed to get package name from {}", download.url))?;
+
+    let directory = package_directory(name).unwrap_or_else(|| {
+        log::warn!("Unknown package {name}, extracting it into the client root");
+        ""
+    });
+
+    let extract_to = write_to.join(directory);
+    fs::create_dir_all(&extract_to).context(format!(
+        "Failed to create package directory: {extract_to:?}"
+    ))?;
+
+    download_file(
+        client,
+        retry,
+        &download,
+        download_dir,
+        &extract_to,
+        limits,
+        read_timeout,
+        TARGET_CONCURRENT_DOWNLOADS,
+        progress,
+    )
+    .await
+    .context(format!("Failed to download package {name}"))?;
+
+    log::debug!("Extracted {name} into {extract_to:?}");
+
+    Ok(())
+}
+
+/// Parse `rbxPkgManifest.txt`. After a `v0` header, each package is listed as four lines: its name, MD5 checksum,
+/// packed size and unpacked size.
+pub fn parse_package_manifest(manifest: &str) -> anyhow::Result<Vec<Package>> {
+    let mut lines = manifest
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+
+    match lines.next() {
+        Some("v0") => {}
+        Some(version) => bail!("Unsupported package manifest version {version:?}"),
+        None => bail!("Package manifest is empty"),
+    }
+
+    let lines: Vec<_> = lines.collect();
+    if lines.len() % 4 != 0 {
+        bail!(
+            "Expected 4 lines per package in package manifest, got {} lines",
+            lines.len()
+        );
+    }
+
+    lines
+        .chunks_exact(4)
+        .map(|package| {
+            let [name, checksum, packed_size, size] = package else {
+                unreachable!("chunks are exactly 4 lines");
+            };
+
+            Ok(Package {
+                name: name.to_string(),
+                checksum: checksum.to_string(),
+                packed_size: packed_size
+                    .parse()
+                    .context(format!("Invalid packed size for {name}: {packed_size:?}"))?,
+                size: size
+                    .parse()
+                    .context(format!("Invalid size for {name}: {size:?}"))?,
+            })
+        })
+        .collect()
+}
+
+/// Get the package name from its download path, e.g. `content-fonts.zip` from
+/// `https://setup.rbxcdn.com/version-abc-content-fonts.zip`.
+fn package_name(download_path: &str) -> Option<&str> {
+    let file_name = download_path.rsplit('/').next()?;
+    let file_name = file_name.strip_prefix("version-")?;
+    let (_, name) = file_name.split_once('-')?;
+    Some(name)
+}
+
+/// Where a package is extracted to, relative to the client directory.
+fn package_directory(name: &str) -> Option<&'static str> {
+    let directory = match name {
+        "RobloxApp.zip" => "",
+        "redist.zip" => "",
+        "WebView2.zip" => "",
+        "shaders.zip" => "shaders/",
+        "ssl.zip" => "ssl/",
+        "WebView2RuntimeInstaller.zip" => "WebView2RuntimeInstaller/",
+
+        "content-avatar.zip" => "content/avatar/",
+        "content-configs.zip" => "content/configs/",
+        "content-fonts.zip" => "content/fonts/",
+        "content-sky.zip" => "content/sky/",
+        "content-sounds.zip" => "content/sounds/",
+        "content-textures2.zip" => "content/textures/",
+        "content-models.zip" => "content/models/",
+
+        "content-textures3.zip" => "PlatformContent/pc/textures/",
+        "content-terrain.zip" => "PlatformContent/pc/terrain/",
+        "content-platform-fonts.zip" => "PlatformContent/pc/fonts/",
+
+        "extracontent-luapackages.zip" => "ExtraContent/LuaPackages/",
+        "extracontent-translations.zip" => "ExtraContent/translations/",
+        "extracontent-models.zip" => "ExtraContent/models/",
+        "extracontent-textures.zip" => "ExtraContent/textures/",
+        "extracontent-places.zip" => "ExtraContent/places/",
+
+        _ => return None,
+    };
+
+    Some(directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use deploy_history::{
+        client_version_info::ClientVersionInfo, domain::Channel, retry::RetryPolicy,
+    };
+    use md5::{Digest, Md5};
+    use mock_roblox::{zip_package, MockRoblox};
+    use reqwest::Client;
+    use tempfile::TempDir;
+
+    use crate::downloader::{
+        platform_impl::{
+            windows::{package_directory, package_name, parse_package_manifest, WindowsDownloader},
+            ClientDownloader, ExtractLimits,
+        },
+        progress::ProgressTracker,
+    };
+    use crate::http::Timeouts;
+
+    const MANIFEST: &str = "v0\r\nRobloxApp.zip\r\n3b1d1a4d2c6f7e8a9b0c1d2e3f4a5b6c\r\n31268829\r\n81212784\r\ncontent-fonts.zip\r\n8f2e1c3b4a5d6e7f8a9b0c1d2e3f4a5b\r\n2113593\r\n3342532\r\nRobloxPlayerLauncher.exe\r\n0a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d\r\n2359560\r\n2359560\r\n";
+
+    #[test]
+    fn parses_package_manifest() {
+        let packages = parse_package_manifest(MANIFEST).unwrap();
+
+        assert_eq!(packages.len(), 3);
+        assert_eq!(packages[0].name, "RobloxApp.zip");
+        assert_eq!(packages[0].checksum, "3b1d1a4d2c6f7e8a9b0c1d2e3f4a5b6c");
+        assert_eq!(packages[0].packed_size, 31268829);
+        assert_eq!(packages[0].size, 81212784);
+        assert_eq!(packages[2].name, "RobloxPlayerLauncher.exe");
+    }
+
+    #[test]
+    fn rejects_malformed_manifests() {
+        assert!(parse_package_manifest("").is_err());
+        assert!(parse_package_manifest("v1\nRobloxApp.zip\na\n1\n1\n").is_err());
+        assert!(parse_package_manifest("v0\nRobloxApp.zip\na\n1\n").is_err());
+        assert!(parse_package_manifest("v0\nRobloxApp.zip\na\nbig\n1\n").is_err());
+    }
+
+    #[test]
+    fn maps_packages_to_directories() {
+        assert_eq!(package_directory("RobloxApp.zip"), Some(""));
+        assert_eq!(
+            package_directory("content-textures2.zip"),
+            Some("content/textures/")
+        );
+        assert_eq!(
+            package_directory("content-textures3.zip"),
+            Some("PlatformContent/pc/textures/")
+        );
+        assert_eq!(package_directory("mystery.zip"), None);
+
+        assert_eq!(
+            package_name("http://localhost/channel/zcanary/version-abc123-content-fonts.zip"),
+            Some("content-fonts.zip")
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_packages_into_place() {
+        let mock = MockRoblox::start();
+        let version_guid = "version-d780cbcde4ab4f52";
+        let packages = [
+            (
+                "RobloxApp.zip",
+                zip_package(&[("RobloxPlayerBeta.exe", b"player")]),
+            ),
+            (
+                "content-fonts.zip",
+                zip_package(&[("families/", b""), ("families/Arial.json", b"{}")]),
+            ),
+            (
+                "shaders.zip",
+                zip_package(&[("shaders_d3d11.pack", b"shaders")]),
+            ),
+        ];
+
+        let mut manifest = "v0\n".to_owned();
+        for (name, package) in &packages {
+            let checksum = format!("{:x}", Md5::digest(package));
+            manifest.push_str(&format!("{name}\n{checksum}\n1\n1\n"));
+            mock.add_file(&format!("/{version_guid}-{name}"), package.to_owned());
+        }
+        manifest.push_str("RobloxPlayerLauncher.exe\nd\n1\n1\n");
+        mock.add_file(&format!("/{version_guid}-rbxPkgManifest.txt"), manifest);
+
+        let client = Client::new();
+        let retry = RetryPolicy::none();
+        let version_info =
+            ClientVersionInfo::new(Channel::Live, "0.553.0.5530470".into(), version_guid.into())
+                .unwrap();
+
+        let download_paths = WindowsDownloader
+            .get_file_download_paths(&client, &retry, &mock.endpoints(), &version_info)
+            .await
+            .unwrap();
+        assert_eq!(download_paths.len(), 3);
+
+        let root_dir = TempDir::new().unwrap();
+        let download_dir = root_dir.path().join("downloads");
+        fs::create_dir(&download_dir).unwrap();
+        let write_to = root_dir.path().join("client");
+        WindowsDownloader
+            .download_files_and_write_to_path(
+                &client,
+                &retry,
+                &mock.endpoints(),
+                download_paths,
+                &download_dir,
+                &write_to,
+                &ExtractLimits::default(),
+                Timeouts::default().read_timeout(),
+                &ProgressTracker::ignored(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read(write_to.join("RobloxPlayerBeta.exe")).unwrap(),
+            b"player"
+        );
+        assert!(write_to.join("content/fonts/families/Arial.json").exists());
+        assert!(write_to.join("shaders/shaders_d3d11.pack").exists());
+        let app_settings = fs::read_to_string(write_to.join("AppSettings.xml")).unwrap();
+        assert!(app_settings.contains(&format!("<BaseUrl>{}</BaseUrl>", mock.url())));
+
+        // Only the extracted files should be left behind.
+        let leftover_zips = fs::read_dir(&write_to)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "zip"))
+            .count();
+        assert_eq!(leftover_zips, 0);
+    }
+
+    #[tokio::test]
+    async fn resumes_after_finished_packages() {
+        let mock = MockRoblox::start();
+        let version_guid = "version-d780cbcde4ab4f52";
+        let packages = [
+            (
+                "RobloxApp.zip",
+                zip_package(&[("RobloxPlayerBeta.exe", b"player")]),
+            ),
+            (
+                "content-fonts.zip",
+                zip_package(&[("families/Arial.json", b"{}")]),
+            ),
+        ];
+
+        let mut manifest = "v0\n".to_owned();
+        for (name, package) in &packages {
+            let checksum = format!("{:x}", Md5::digest(package));
+            manifest.push_str(&format!("{name}\n{checksum}\n1\n1\n"));
+            mock.add_file(&format!("/{version_guid}-{name}"), package.to_owned());
+        }
+        mock.add_file(&format!("/{version_guid}-rbxPkgManifest.txt"), manifest);
+
+        let client = Client::new();
+        let retry = RetryPolicy::none();
+        let version_info =
+            ClientVersionInfo::new(Channel::Live, "0.553.0.5530470".into(), version_guid.into())
+                .unwrap();
+
+        let download_paths = WindowsDownloader
+            .get_file_download_paths(&client, &retry, &mock.endpoints(), &version_info)
+            .await
+            .unwrap();
+
+        let root_dir = TempDir::new().unwrap();
+        let download_dir = root_dir.path().join("downloads");
+        fs::create_dir(&download_dir).unwrap();
+
+        // The launcher is closed after RobloxApp.zip finished, but before the rest of the client did.
+        let app_package = download_paths
+            .iter()
+            .filter(|download| download.url.ends_with("RobloxApp.zip"))
+            .cloned()
+            .collect();
+        WindowsDownloader
+            .download_files_and_write_to_path(
+                &client,
+                &retry,
+                &mock.endpoints(),
+                app_package,
+                &download_dir,
+                &root_dir.path().join("interrupted"),
+                &ExtractLimits::default(),
+                Timeouts::default().read_timeout(),
+                &ProgressTracker::ignored(),
+            )
+            .await
+            .unwrap();
+
+        let app_requests = |mock: &MockRoblox| {
+            mock.requests()
+                .iter()
+                .filter(|request| request.path.ends_with("RobloxApp.zip"))
+                .count()
+        };
+        let requests_before = app_requests(&mock);
+
+        // The next attempt starts over in a fresh staging directory.
+        let write_to = root_dir.path().join("client");
+        WindowsDownloader
+            .download_files_and_write_to_path(
+                &client,
+                &retry,
+                &mock.endpoints(),
+                download_paths,
+                &download_dir,
+                &write_to,
+                &ExtractLimits::default(),
+                Timeouts::default().read_timeout(),
+                &ProgressTracker::ignored(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(app_requests(&mock), requests_before);
+        assert_eq!(
+            fs::read(write_to.join("RobloxPlayerBeta.exe")).unwrap(),
+            b"player"
+        );
+        assert!(write_to.join("content/fonts/families/Arial.json").exists());
     }
 }
@@ -0,0 +1,193 @@
+//! Tracks how far along a client download is, so the UI can show a real progress bar.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Reports are throttled to this, the UI doesn't need every network chunk.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A snapshot of a client download, across every file in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// The file which most recently received bytes.
+    pub file: String,
+    pub bytes_received: u64,
+    /// Grows as the size of each file becomes known.
+    pub total_bytes: u64,
+    /// Average rate since the download started. Bytes from a resumed download aren't counted.
+    pub bytes_per_second: u64,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    sizes: HashMap<String, u64>,
+    file: String,
+    bytes_received: u64,
+    /// Bytes received over the network since the download started, for working out the rate.
+    bytes_transferred: u64,
+    started: Instant,
+    last_reported: Option<Instant>,
+}
+
+/// Tallies bytes received for a client download, and hands snapshots to `report`. Cheap to clone, clones share the
+/// same tally.
+#[derive(Clone)]
+pub struct ProgressTracker {
+    state: Arc<Mutex<ProgressState>>,
+    report: Arc<dyn Fn(DownloadProgress) + Send + Sync>,
+}
+
+impl ProgressTracker {
+    pub fn new(report: impl Fn(DownloadProgress) + Send + Sync + 'static) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ProgressState {
+                sizes: HashMap::new(),
+                file: String::new(),
+                bytes_received: 0,
+                bytes_transferred: 0,
+                started: Instant::now(),
+                last_reported: None,
+            })),
+            report: Arc::new(report),
+        }
+    }
+
+    /// A tracker which doesn't report anywhere.
+    pub fn ignored() -> Self {
+        Self::new(|_| {})
+    }
+
+    /// Set the size of the file at `url`, replacing any size set before.
+    pub fn set_size(&self, url: &str, size: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .sizes
+            .insert(url.to_owned(), size);
+    }
+
+    /// Count bytes downloaded by an earlier, interrupted download.
+    pub fn resumed(&self, url: &str, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes_received += bytes;
+        state.file = file_name(url).to_owned();
+    }
+
+    /// Count bytes received over the network.
+    pub fn received(&self, url: &str, bytes: u64) {
+        let progress = {
+            let mut state = self.state.lock().unwrap();
+            state.bytes_received += bytes;
+            state.bytes_transferred += bytes;
+            state.file = file_name(url).to_owned();
+
+            let now = Instant::now();
+            if state
+                .last_reported
+                .is_some_and(|last_reported| now - last_reported < REPORT_INTERVAL)
+            {
+                return;
+            }
+
+            state.last_reported = Some(now);
+            snapshot(&state)
+        };
+
+        (self.report)(progress);
+    }
+
+    /// Take back bytes which were received but thrown away, e.g. from a chunk that failed part way through.
+    pub fn discarded(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes_received = state.bytes_received.saturating_sub(bytes);
+    }
+
+    /// Report the latest progress now, regardless of when it was last reported.
+    pub fn flush(&self) {
+        let progress = snapshot(&self.state.lock().unwrap());
+        (self.report)(progress);
+    }
+}
+
+impl fmt::Debug for ProgressTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+fn snapshot(state: &ProgressState) -> DownloadProgress {
+    let elapsed = state.started.elapsed().as_secs_f64();
+    let bytes_per_second = if elapsed > 0.0 {
+        (state.bytes_transferred as f64 / elapsed) as u64
+    } else {
+        0
+    };
+
+    DownloadProgress {
+        file: state.file.to_owned(),
+        bytes_received: state.bytes_received,
+        total_bytes: state.sizes.values().sum(),
+        bytes_per_second,
+    }
+}
+
+fn file_name(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::downloader::progress::{DownloadProgress, ProgressTracker};
+
+    fn get_tracker() -> (ProgressTracker, Arc<Mutex<Vec<DownloadProgress>>>) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let tracker_reports = Arc::clone(&reports);
+        let tracker = ProgressTracker::new(move |progress| {
+            tracker_reports.lock().unwrap().push(progress);
+        });
+
+        (tracker, reports)
+    }
+
+    #[test]
+    fn tallies_every_file() {
+        let (tracker, reports) = get_tracker();
+        tracker.set_size("https://setup.rbxcdn.com/version-abc-RobloxApp.zip", 100);
+        tracker.set_size("https://setup.rbxcdn.com/version-abc-shaders.zip", 50);
+
+        tracker.resumed("https://setup.rbxcdn.com/version-abc-RobloxApp.zip", 40);
+        tracker.received("https://setup.rbxcdn.com/version-abc-shaders.zip", 10);
+        tracker.discarded(5);
+        tracker.flush();
+
+        let reports = reports.lock().unwrap();
+        let last = reports.last().unwrap();
+        assert_eq!(last.file, "version-abc-shaders.zip");
+        assert_eq!(last.bytes_received, 45);
+        assert_eq!(last.total_bytes, 150);
+    }
+
+    #[test]
+    fn throttles_reports() {
+        let (tracker, reports) = get_tracker();
+        tracker.set_size("https://setup.rbxcdn.com/file.zip", 1000);
+
+        for _ in 0..100 {
+            tracker.received("https://setup.rbxcdn.com/file.zip", 10);
+        }
+
+        // Only the first report gets through, the rest are too soon after it.
+        assert_eq!(reports.lock().unwrap().len(), 1);
+
+        tracker.flush();
+        assert_eq!(reports.lock().unwrap().last().unwrap().bytes_received, 1000);
+    }
+}
@@ -0,0 +1,334 @@
+//! Tidies up after a launcher which crashed or was killed part way through installing a client.
+//!
+//! Anything that can still be used is kept: partial downloads are resumed by the next download of the same version,
+//! and installed versions missing from disk are forgotten so the client falls back on (or reinstalls) another one.
+//! Everything else is deleted. Each is logged, so it's clear from the logs what happened to a broken install.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use super::{client_lock::ClientLock, install, store};
+
+/// Where launchers from before versions were installed side by side downloaded packages, inside `client/`.
+const LEGACY_TEMP_DIR: &str = "temp";
+
+/// Clean up files left in `client/` before the lock is loaded, so nothing left behind gets mistaken for part of the
+/// client. Unfinished installs are left to [`reconcile`].
+pub fn clean_up_client_dir(root_dir: &Path) -> anyhow::Result<()> {
+    let client_dir = root_dir.join(install::CLIENT_DIR);
+    let Ok(entries) = fs::read_dir(&client_dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+
+        let leftover = if file_name == LEGACY_TEMP_DIR {
+            "download directory"
+        } else if file_name.ends_with(".zip") {
+            "package"
+        } else if file_name.ends_with(".lock.tmp") {
+            "client.lock"
+        } else {
+            continue;
+        };
+
+        log::info!("Cleaning up {leftover} left by an interrupted install: {path:?}");
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.context(format!("Failed to delete {path:?}"))?;
+    }
+
+    Ok(())
+}
+
+/// Make the lock and what's on disk agree again. Unfinished installs are deleted, versions the lock records which are
+/// no longer installed are forgotten, and versions installed in `client/` which the lock doesn't know about are
+/// deleted. Returns the lock, unless nothing it recorded is still installed.
+///
+/// Only call this while holding the [`UpdateLock`](super::UpdateLock), since another launcher's install in progress
+/// looks just like an unfinished one.
+pub fn reconcile(
+    root_dir: &Path,
+    client_lock: Option<ClientLock>,
+) -> anyhow::Result<Option<ClientLock>> {
+    install::recover(&root_dir.join(install::CLIENT_DIR))?;
+
+    let client_lock = match client_lock {
+        Some(mut client_lock) => {
+            let missing = client_lock.forget_missing(root_dir);
+            for installed in &missing {
+                log::warn!(
+                    "Client {} is recorded in client.lock but isn't installed, forgetting it",
+                    installed.version
+                );
+            }
+
+            if client_lock.versions.is_empty() {
+                log::warn!(
+                    "No client recorded in client.lock is installed, it will be installed again"
+                );
+
+                let lock_path = root_dir.join(install::CLIENT_DIR).join("client.lock");
+                fs::remove_file(&lock_path).context(format!("Failed to delete {lock_path:?}"))?;
+                None
+            } else {
+                if !missing.is_empty() {
+                    client_lock
+                        .write_lock_to_path(root_dir)
+                        .context("Failed to write reconciled client.lock")?;
+                }
+                Some(client_lock)
+            }
+        }
+        None => None,
+    };
+
+    remove_unknown_versions(root_dir, client_lock.as_ref())?;
+    clean_up_downloads(root_dir, client_lock.as_ref());
+
+    Ok(client_lock)
+}
+
+/// Make the shared store in `versions_dir` agree with the lock of the app in `root_dir`, which has been reconciled
+/// already. Unfinished installs in the store are deleted, references to versions the lock doesn't record are released,
+/// and versions nothing references are deleted.
+///
+/// Like [`reconcile`], only call this while holding the [`UpdateLock`](super::UpdateLock) on the store.
+pub fn reconcile_store(
+    root_dir: &Path,
+    versions_dir: &Path,
+    client_lock: Option<&ClientLock>,
+) -> anyhow::Result<()> {
+    if !versions_dir.exists() {
+        return Ok(());
+    }
+
+    install::recover(versions_dir)?;
+
+    // Versions are referenced before the lock is written, so a crash in between leaves a reference nothing will
+    // release.
+    let known = |version_guid: &str| {
+        client_lock.is_some_and(|client_lock| {
+            client_lock.store.as_deref() == Some(versions_dir)
+                && client_lock.installed(version_guid).is_some()
+        })
+    };
+
+    for version_guid in store::referenced_by(versions_dir, root_dir)? {
+        if known(&version_guid) {
+            continue;
+        }
+
+        log::info!(
+            "Releasing client {version_guid} in the shared store, which isn't recorded in client.lock"
+        );
+        store::release(versions_dir, &version_guid, root_dir)?;
+    }
+
+    store::collect_garbage(versions_dir)?;
+
+    Ok(())
+}
+
+/// Versions are swapped into `client/` before the lock is written, so a crash in between leaves a version nothing
+/// knows about.
+fn remove_unknown_versions(
+    root_dir: &Path,
+    client_lock: Option<&ClientLock>,
+) -> anyhow::Result<()> {
+    let client_dir = root_dir.join(install::CLIENT_DIR);
+    let Ok(entries) = fs::read_dir(&client_dir) else {
+        return Ok(());
+    };
+
+    let known = |version_guid: &str| {
+        client_lock.is_some_and(|client_lock| {
+            client_lock.store.is_none() && client_lock.installed(version_guid).is_some()
+        })
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let version_guid = entry.file_name().to_string_lossy().into_owned();
+        if !path.is_dir() || known(&version_guid) {
+            continue;
+        }
+
+        log::info!("Cleaning up client {version_guid}, which isn't recorded in client.lock");
+        install::remove_version(&client_dir, &version_guid)?;
+    }
+
+    Ok(())
+}
+
+/// Downloads are only deleted once the version is installed, so one for an installed version was interrupted just
+/// before it was cleaned up. Any other download is kept to be resumed.
+fn clean_up_downloads(root_dir: &Path, client_lock: Option<&ClientLock>) {
+    let Ok(entries) = fs::read_dir(root_dir.join(install::DOWNLOADS_DIR)) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let version_guid = entry.file_name().to_string_lossy().into_owned();
+
+        let installed =
+            client_lock.is_some_and(|client_lock| client_lock.installed(&version_guid).is_some());
+        if !installed {
+            log::info!("Keeping partial download of {version_guid} to resume: {path:?}");
+            continue;
+        }
+
+        log::info!("Cleaning up download of installed client {version_guid}: {path:?}");
+        if let Err(error) = fs::remove_dir_all(&path) {
+            log::warn!("Failed to delete download {path:?}: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::Path};
+
+    use deploy_history::{
+        client_version_info::ClientVersionInfo,
+        domain::{BinaryType, Channel},
+    };
+    use tempfile::TempDir;
+
+    use crate::downloader::{
+        client_lock::{ClientLock, InstalledVersion},
+        recovery::{clean_up_client_dir, reconcile, reconcile_store},
+        store::{add_reference, referenced_by},
+    };
+
+    fn get_version(version: &str, version_guid: &str) -> InstalledVersion {
+        let version =
+            ClientVersionInfo::new(Channel::Live, version.into(), version_guid.into()).unwrap();
+        InstalledVersion::new(version, Vec::new(), BTreeMap::new())
+    }
+
+    fn install(root_dir: &Path, version_guid: &str) {
+        fs::create_dir_all(root_dir.join("client").join(version_guid)).unwrap();
+    }
+
+    #[test]
+    fn cleans_up_leftover_files() {
+        let root_dir = TempDir::new().unwrap();
+        let client_dir = root_dir.path().join("client");
+        fs::create_dir_all(client_dir.join("temp")).unwrap();
+        fs::write(client_dir.join("temp/d41d8cd9.zip"), "partial").unwrap();
+        fs::write(client_dir.join("d41d8cd9.zip"), "partial").unwrap();
+        fs::write(client_dir.join("client.lock.tmp"), "half written").unwrap();
+        install(root_dir.path(), "version-a");
+
+        clean_up_client_dir(root_dir.path()).unwrap();
+
+        let mut remaining = fs::read_dir(&client_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["version-a"]);
+    }
+
+    #[test]
+    fn reconciles_lock_with_installed_versions() {
+        let root_dir = TempDir::new().unwrap();
+        let root_dir = root_dir.path();
+
+        let mut lock = ClientLock::new(
+            BinaryType::MacPlayer,
+            get_version("0.553.0.5530470", "version-a"),
+            None,
+        );
+        lock.install(get_version("0.554.0.5540123", "version-b"));
+        lock.write_lock_to_path(root_dir).unwrap();
+
+        // The current version has gone missing, and one the lock doesn't know about has turned up.
+        install(root_dir, "version-a");
+        install(root_dir, "version-c");
+        fs::create_dir_all(root_dir.join("client/version-e.staging")).unwrap();
+
+        // One download finished but wasn't cleaned up, the other was interrupted.
+        fs::create_dir_all(root_dir.join("downloads/version-a")).unwrap();
+        fs::create_dir_all(root_dir.join("downloads/version-d")).unwrap();
+
+        let lock = reconcile(root_dir, Some(lock)).unwrap().unwrap();
+        assert_eq!(lock.current, "version-a");
+        assert!(lock.installed("version-b").is_none());
+        assert_eq!(
+            ClientLock::get(root_dir).unwrap().unwrap().current,
+            "version-a"
+        );
+
+        assert!(root_dir.join("client/version-a").exists());
+        assert!(!root_dir.join("client/version-c").exists());
+        assert!(!root_dir.join("client/version-e.staging").exists());
+        assert!(!root_dir.join("downloads/version-a").exists());
+        assert!(root_dir.join("downloads/version-d").exists());
+    }
+
+    #[test]
+    fn forgets_lock_without_installed_versions() {
+        let root_dir = TempDir::new().unwrap();
+        let root_dir = root_dir.path();
+
+        let lock = ClientLock::new(
+            BinaryType::MacPlayer,
+            get_version("0.553.0.5530470", "version-a"),
+            None,
+        );
+        lock.write_lock_to_path(root_dir).unwrap();
+
+        assert!(reconcile(root_dir, Some(lock)).unwrap().is_none());
+        assert!(ClientLock::get(root_dir).unwrap().is_none());
+    }
+
+    #[test]
+    fn reconciles_shared_store_with_lock() {
+        let root_dir = TempDir::new().unwrap();
+        let root_dir = root_dir.path();
+        let other_app = TempDir::new().unwrap();
+        let versions_dir = TempDir::new().unwrap();
+        let versions_dir = versions_dir.path();
+
+        let lock = ClientLock::new(
+            BinaryType::MacPlayer,
+            get_version("0.553.0.5530470", "version-a"),
+            Some(versions_dir.to_owned()),
+        );
+
+        // version-b was referenced but the lock was never written, version-c was moved into the store but never
+        // referenced, and version-d was still being installed.
+        for version_guid in ["version-a", "version-b", "version-c", "version-d.staging"] {
+            fs::create_dir_all(versions_dir.join(version_guid)).unwrap();
+        }
+        add_reference(versions_dir, "version-a", root_dir).unwrap();
+        add_reference(versions_dir, "version-b", root_dir).unwrap();
+
+        // Another app's versions are left alone.
+        fs::create_dir_all(versions_dir.join("version-e")).unwrap();
+        add_reference(versions_dir, "version-e", other_app.path()).unwrap();
+
+        reconcile_store(root_dir, versions_dir, Some(&lock)).unwrap();
+
+        assert_eq!(
+            referenced_by(versions_dir, root_dir).unwrap(),
+            ["version-a"]
+        );
+        let mut remaining = fs::read_dir(versions_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["references.toml", "version-a", "version-e"]);
+    }
+}
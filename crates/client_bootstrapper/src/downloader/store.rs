@@ -0,0 +1,252 @@
+//! A client store shared by every Packer game on the machine, so players who own several of them only download each
+//! client version once.
+//!
+//! Versions are installed in `<store>/<binary_type>/<version_guid>/`. Every app using a version holds a reference to
+//! it in `<store>/<binary_type>/references.toml`, and a version is deleted once nothing references it.
+//!
+//! A new version is moved into the store before the app installing it records its reference, so the store must be
+//! locked with an [`UpdateLock`](super::UpdateLock) while it's changed. Otherwise another launcher could delete the
+//! version in between.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use deploy_history::domain::BinaryType;
+use serde::{Deserialize, Serialize};
+
+use super::install;
+
+const REFERENCES_FILE: &str = "references.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedStore {
+    dir: PathBuf,
+}
+
+/// The apps referencing each version in a store.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct References {
+    /// App root directories, by `version_guid`.
+    versions: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+impl SharedStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The store in the user's data directory, if the OS has one.
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|data_dir| Self::new(data_dir.join("Packer/clients")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where versions of `binary_type` are installed.
+    pub fn versions_dir(&self, binary_type: &BinaryType) -> PathBuf {
+        self.dir.join(binary_type.to_string())
+    }
+}
+
+/// Record that the app in `app_dir` uses `version_guid` from the store in `versions_dir`.
+pub fn add_reference(
+    versions_dir: &Path,
+    version_guid: &str,
+    app_dir: &Path,
+) -> anyhow::Result<()> {
+    let mut references = read_references(versions_dir)?;
+    references
+        .versions
+        .entry(version_guid.to_owned())
+        .or_default()
+        .insert(app_dir.to_owned());
+
+    write_references(versions_dir, &references)
+}
+
+/// Drop the reference the app in `app_dir` holds to `version_guid`, then delete any versions which are no longer used.
+pub fn release(versions_dir: &Path, version_guid: &str, app_dir: &Path) -> anyhow::Result<()> {
+    let mut references = read_references(versions_dir)?;
+    if let Some(apps) = references.versions.get_mut(version_guid) {
+        apps.remove(app_dir);
+    }
+
+    write_references(versions_dir, &references)?;
+    collect_garbage(versions_dir)?;
+
+    Ok(())
+}
+
+/// The versions the app in `app_dir` holds a reference to.
+pub fn referenced_by(versions_dir: &Path, app_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let references = read_references(versions_dir)?;
+    let versions = references
+        .versions
+        .into_iter()
+        .filter(|(_, apps)| apps.contains(app_dir))
+        .map(|(version_guid, _)| version_guid)
+        .collect();
+
+    Ok(versions)
+}
+
+/// Delete every version in the store which no app references. Apps which have since been deleted don't count, and nor
+/// do versions missing from the references entirely, e.g. because a launcher was closed before it recorded its
+/// reference. Returns the versions deleted.
+pub fn collect_garbage(versions_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut references = read_references(versions_dir)?;
+    let mut deleted = Vec::new();
+
+    for (version_guid, apps) in &mut references.versions {
+        apps.retain(|app_dir| app_dir.exists());
+
+        if apps.is_empty() {
+            log::info!("Removing unused client {version_guid} from shared store");
+            install::remove_version(versions_dir, version_guid)?;
+            deleted.push(version_guid.to_owned());
+        }
+    }
+
+    references
+        .versions
+        .retain(|version_guid, _| !deleted.contains(version_guid));
+    write_references(versions_dir, &references)?;
+
+    for version_guid in unreferenced_versions(versions_dir, &references)? {
+        log::info!("Removing unreferenced client {version_guid} from shared store");
+        install::remove_version(versions_dir, &version_guid)?;
+        deleted.push(version_guid);
+    }
+
+    Ok(deleted)
+}
+
+/// Version directories in the store which aren't in `references`. Anything else in the store, like the references
+/// themselves or unfinished installs, has an extension.
+fn unreferenced_versions(
+    versions_dir: &Path,
+    references: &References,
+) -> anyhow::Result<Vec<String>> {
+    let entries = fs::read_dir(versions_dir)
+        .context(format!("Failed to read shared store {versions_dir:?}"))?;
+
+    let versions = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.extension().is_none())
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_owned()))
+        .filter(|version_guid| !references.versions.contains_key(version_guid))
+        .collect();
+
+    Ok(versions)
+}
+
+fn read_references(versions_dir: &Path) -> anyhow::Result<References> {
+    let path = versions_dir.join(REFERENCES_FILE);
+    if !path.exists() {
+        return Ok(References::default());
+    }
+
+    let references = fs::read_to_string(&path).context(format!(
+        "Failed to read shared store references at {path:?}"
+    ))?;
+    let references = toml::from_str::<References>(&references)
+        .context("Failed to parse shared store references")?;
+
+    Ok(references)
+}
+
+/// Written beside the old references then renamed over them, so they're never left half written.
+fn write_references(versions_dir: &Path, references: &References) -> anyhow::Result<()> {
+    fs::create_dir_all(versions_dir)
+        .context(format!("Failed to create shared store {versions_dir:?}"))?;
+
+    let path = versions_dir.join(REFERENCES_FILE);
+    let temp_path = path.with_extension("toml.tmp");
+
+    let references = toml::to_string_pretty(references)
+        .context("Failed to convert shared store references to string")?;
+
+    fs::write(&temp_path, references).context("Failed to write shared store references")?;
+    fs::rename(&temp_path, &path).context("Failed to replace shared store references")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use deploy_history::domain::BinaryType;
+    use tempfile::TempDir;
+
+    use crate::downloader::{
+        store::{add_reference, collect_garbage, release},
+        SharedStore, UpdateLock,
+    };
+
+    #[test]
+    fn deletes_unreferenced_versions() {
+        let store_dir = TempDir::new().unwrap();
+        let store_dir = store_dir.path();
+        let first_app = TempDir::new().unwrap();
+        let second_app = TempDir::new().unwrap();
+        fs::create_dir_all(store_dir.join("version-a")).unwrap();
+
+        add_reference(store_dir, "version-a", first_app.path()).unwrap();
+        add_reference(store_dir, "version-a", second_app.path()).unwrap();
+
+        release(store_dir, "version-a", first_app.path()).unwrap();
+        assert!(store_dir.join("version-a").exists());
+
+        release(store_dir, "version-a", second_app.path()).unwrap();
+        assert!(!store_dir.join("version-a").exists());
+    }
+
+    #[test]
+    fn ignores_deleted_apps() {
+        let store_dir = TempDir::new().unwrap();
+        let store_dir = store_dir.path();
+        let app = TempDir::new().unwrap();
+        fs::create_dir_all(store_dir.join("version-a")).unwrap();
+        fs::create_dir_all(store_dir.join("version-b")).unwrap();
+
+        add_reference(store_dir, "version-a", app.path()).unwrap();
+        add_reference(store_dir, "version-b", &store_dir.join("uninstalled-app")).unwrap();
+
+        assert_eq!(collect_garbage(store_dir).unwrap(), ["version-b"]);
+        assert!(store_dir.join("version-a").exists());
+        assert!(!store_dir.join("version-b").exists());
+    }
+
+    #[tokio::test]
+    async fn deletes_versions_without_references() {
+        let store_dir = TempDir::new().unwrap();
+        let shared_store = SharedStore::new(store_dir.path());
+        let versions_dir = shared_store.versions_dir(&BinaryType::WindowsPlayer);
+        let app = TempDir::new().unwrap();
+
+        // A launcher was closed after moving version-b into the store, but before it recorded its reference.
+        fs::create_dir_all(versions_dir.join("version-a")).unwrap();
+        fs::create_dir_all(versions_dir.join("version-b")).unwrap();
+        fs::create_dir_all(versions_dir.join("version-c.staging")).unwrap();
+        add_reference(&versions_dir, "version-a", app.path()).unwrap();
+
+        let _update_lock = UpdateLock::acquire(app.path(), Some(&shared_store), || {
+            panic!("Nothing else holds the lock")
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(collect_garbage(&versions_dir).unwrap(), ["version-b"]);
+        assert!(versions_dir.join("version-a").exists());
+        assert!(!versions_dir.join("version-b").exists());
+        assert!(versions_dir.join("version-c.staging").exists());
+    }
+}
@@ -0,0 +1,166 @@
+//! Keeps two launchers from updating the same client at once, e.g. when a game is opened twice, or two games share a
+//! client store. Whichever gets there first updates the client while the other waits, then uses what it installed.
+//!
+//! The locks are advisory OS file locks, so they're released if a launcher crashes or is killed while holding one.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Context;
+use fs2::FileExt;
+use tokio::time::sleep;
+
+use super::{install, SharedStore};
+
+/// The file locked in each directory. It's never written to, and is left behind when the lock is released.
+pub const UPDATE_LOCK_FILE: &str = "update.lock";
+
+/// How often to try again while another launcher holds the lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Held while the client is being updated, and released when dropped.
+#[derive(Debug)]
+pub struct UpdateLock {
+    _files: Vec<File>,
+}
+
+impl UpdateLock {
+    /// Lock the client of the app in `root_dir`, and the shared store if it uses one. If another launcher holds
+    /// either, `on_wait` is called and this waits until it's finished.
+    pub async fn acquire(
+        root_dir: &Path,
+        shared_store: Option<&SharedStore>,
+        on_wait: impl FnOnce(),
+    ) -> anyhow::Result<Self> {
+        // Always locked in the same order, so two launchers can never each be waiting on the other.
+        let mut dirs = vec![root_dir.join(install::CLIENT_DIR)];
+        dirs.extend(shared_store.map(|shared_store| shared_store.dir().to_owned()));
+
+        let mut on_wait = Some(on_wait);
+        let mut files = Vec::new();
+
+        for dir in dirs {
+            let file = open_lock_file(&dir)?;
+
+            loop {
+                match file.try_lock_exclusive() {
+                    Ok(()) => break,
+                    Err(error) if is_contended(&error) => {
+                        if let Some(on_wait) = on_wait.take() {
+                            log::info!(
+                                "Another launcher is updating {dir:?}, waiting for it to finish"
+                            );
+                            on_wait();
+                        }
+
+                        sleep(RETRY_INTERVAL).await;
+                    }
+                    Err(error) => {
+                        return Err(error).context(format!("Failed to lock {dir:?} for updating"))
+                    }
+                }
+            }
+
+            log::debug!("Locked {dir:?} for updating");
+            files.push(file);
+        }
+
+        Ok(Self { _files: files })
+    }
+}
+
+fn open_lock_file(dir: &Path) -> anyhow::Result<File> {
+    fs::create_dir_all(dir).context(format!("Failed to create {dir:?}"))?;
+
+    let path = dir.join(UPDATE_LOCK_FILE);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open update lock at {path:?}"))
+}
+
+fn is_contended(error: &io::Error) -> bool {
+    error.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tempfile::TempDir;
+    use tokio::time::sleep;
+
+    use crate::downloader::{update_lock::UpdateLock, SharedStore};
+
+    #[tokio::test]
+    async fn waits_for_other_launcher() {
+        let root_dir = TempDir::new().unwrap();
+        let root_dir = root_dir.path().to_owned();
+
+        let first = UpdateLock::acquire(&root_dir, None, || panic!("Nothing else holds the lock"))
+            .await
+            .unwrap();
+
+        let waited = Arc::new(AtomicBool::new(false));
+        let second = tokio::spawn({
+            let waited = Arc::clone(&waited);
+            async move {
+                UpdateLock::acquire(&root_dir, None, || waited.store(true, Ordering::SeqCst))
+                    .await
+                    .unwrap()
+            }
+        });
+
+        sleep(Duration::from_millis(500)).await;
+        assert!(waited.load(Ordering::SeqCst));
+        assert!(!second.is_finished());
+
+        drop(first);
+        second.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn locks_shared_store() {
+        let store_dir = TempDir::new().unwrap();
+        let shared_store = SharedStore::new(store_dir.path());
+        let first_app = TempDir::new().unwrap();
+        let second_app = TempDir::new().unwrap();
+        let second_app = second_app.path().to_owned();
+
+        let first = UpdateLock::acquire(first_app.path(), Some(&shared_store), || {
+            panic!("Nothing else holds the lock")
+        })
+        .await
+        .unwrap();
+
+        let waited = Arc::new(AtomicBool::new(false));
+        let second = tokio::spawn({
+            let waited = Arc::clone(&waited);
+            async move {
+                UpdateLock::acquire(&second_app, Some(&shared_store), || {
+                    waited.store(true, Ordering::SeqCst)
+                })
+                .await
+                .unwrap()
+            }
+        });
+
+        sleep(Duration::from_millis(500)).await;
+        assert!(waited.load(Ordering::SeqCst));
+        assert!(!second.is_finished());
+
+        drop(first);
+        second.await.unwrap();
+    }
+}
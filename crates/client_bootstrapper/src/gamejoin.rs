@@ -1,60 +1,74 @@
-use std::{
-    path::Path,
-    process::{Command, Stdio},
-};
+use std::process::Stdio;
 
 use anyhow::{bail, Context};
+use deploy_history::{endpoints::Endpoints, retry::RetryPolicy};
 use rand::{thread_rng, Rng};
 use reqwest::{
     header::{HeaderMap, REFERER},
     Client,
 };
 
-use crate::authentication::AuthenticationContext;
+use crate::{authentication::AuthenticationContext, downloader::InstalledClient, http::Timeouts};
 
 /// Handles everything around negotiating the game joining process with Roblox (getting an authentication
 /// ticket, etc).
 #[derive(Debug)]
 pub struct GamejoinContext<'a> {
     client: Client,
+    endpoints: Endpoints,
+    /// How requests which fail for transient reasons are retried.
+    retry: RetryPolicy,
     auth_context: &'a AuthenticationContext,
 }
 
 impl<'a> GamejoinContext<'a> {
-    pub fn new(auth_context: &'a AuthenticationContext) -> anyhow::Result<Self> {
-        let client = Client::builder()
-            .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/107.0.0.0 Safari/537.36")
-            .referer(false)
-            .build()?;
+    pub fn new(
+        auth_context: &'a AuthenticationContext,
+        endpoints: Endpoints,
+    ) -> anyhow::Result<Self> {
+        let client = Timeouts::default().lookup_client()?;
 
         Ok(Self {
             client,
+            endpoints,
+            retry: RetryPolicy::default(),
             auth_context,
         })
     }
 
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give up on requests to Roblox after `timeouts` instead of the defaults.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> anyhow::Result<Self> {
+        self.client = timeouts
+            .lookup_client()
+            .context("Failed to build HTTP client")?;
+        Ok(self)
+    }
+
     /// Launch the game client into the specified experience!
     pub async fn launch_roblox_client(
         &self,
         place_id: &u64,
-        roblox_player: &Path,
+        roblox_player: &InstalledClient,
     ) -> anyhow::Result<()> {
-        if !roblox_player.exists() {
-            bail!("Can't launch the Roblox client because the player does not exist at {roblox_player:?}");
-        }
-
-        if !roblox_player.is_file() {
-            bail!("Can't launch the Roblox client because the player is not a file ({roblox_player:?})");
+        let executable = &roblox_player.executable;
+        if !executable.is_file() {
+            bail!("Can't launch the Roblox client because the player does not exist at {executable:?}");
         }
 
-        log::debug!("Found RobloxPlayer at: {roblox_player:?}");
+        log::debug!("Found RobloxPlayer at: {executable:?}");
 
         let application_args = self
             .generate_application_args(place_id)
             .await
             .context("Failed to generate application args")?;
 
-        let handle = Command::new(roblox_player)
+        let handle = roblox_player
+            .command()
             .args(application_args)
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -78,7 +92,7 @@ impl<'a> GamejoinContext<'a> {
         // could trip up the client.
         let tracker_id = thread_rng().gen_range(100_000_000..999_999_999).to_string();
 
-        let script_url = format!("https://assetgame.roblox.com/game/PlaceLauncher.ashx?request=RequestGame&browserTrackerId={tracker_id}&placeId={place_id}&isPlayTogetherGame=false");
+        let script_url = format!("{}/game/PlaceLauncher.ashx?request=RequestGame&browserTrackerId={tracker_id}&placeId={place_id}&isPlayTogetherGame=false", self.endpoints.assetgame);
 
         #[rustfmt::skip]
         let application_args = [
@@ -101,23 +115,26 @@ impl<'a> GamejoinContext<'a> {
 
     /// An authentication ticket is required for initiating the gamejoin process (handled by the Roblox client).
     pub async fn create_authentication_ticket(&self) -> anyhow::Result<String> {
+        let cookie_domain = self
+            .endpoints
+            .cookie_domain()
+            .context(format!("Malformed www endpoint: {}", self.endpoints.www))?;
         let auth_cookie = self
             .auth_context
-            .get_roblosecurity_cookie()
+            .get_roblosecurity_cookie(&cookie_domain)
             .context("Failed to get authentication cookie")?
             .to_string();
 
         let mut headers = HeaderMap::new();
-        headers.insert(REFERER, "https://www.roblox.com".try_into()?);
+        headers.insert(REFERER, self.endpoints.www.as_str().try_into()?);
         headers.insert(reqwest::header::CONTENT_LENGTH, 0.into());
         headers.insert("Cookie", auth_cookie.try_into()?);
 
         // First we need to get a X-CSRF token
+        let csrf_url = format!("{}/v2/logout", self.endpoints.auth);
         let csrf_response = self
-            .client
-            .post("https://auth.roblox.com/v2/logout")
-            .headers(headers.clone())
-            .send()
+            .retry
+            .send(|| self.client.post(&csrf_url).headers(headers.clone()))
             .await
             .context("Failed to send CSRF token request")?;
 
@@ -129,11 +146,10 @@ impl<'a> GamejoinContext<'a> {
         log::debug!("Got X-CSRF token: {csrf_token:?}");
         headers.insert("x-csrf-token", csrf_token.to_owned());
 
+        let ticket_url = format!("{}/v1/authentication-ticket", self.endpoints.auth);
         let ticket_response = self
-            .client
-            .post("https://auth.roblox.com/v1/authentication-ticket")
-            .headers(headers)
-            .send()
+            .retry
+            .send(|| self.client.post(&ticket_url).headers(headers.clone()))
             .await
             .context("Failed to send request for authentication ticket")?;
 
@@ -0,0 +1,59 @@
+//! The HTTP clients requests to Roblox are made with.
+
+use std::time::Duration;
+
+use reqwest::{Client, ClientBuilder};
+use serde::{Deserialize, Serialize};
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/107.0.0.0 Safari/537.36";
+
+/// How long requests to Roblox can take before giving up. Without these, a request which stalls (e.g. on Wi-Fi
+/// without internet, or through a hung proxy) waits for the OS to give up on it, which can take minutes.
+///
+/// A request which times out is retried like a dropped connection.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// How long to wait for a connection to be made, in milliseconds.
+    pub connect_ms: u64,
+    /// How long a lookup, like the latest client version or the deploy history, can take in total, in milliseconds.
+    /// Downloads aren't held to this, since they take as long as the player's connection needs.
+    pub request_ms: u64,
+    /// How long a download can go without receiving anything before it's retried, in milliseconds.
+    pub read_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect_ms: 10_000,
+            request_ms: 30_000,
+            read_ms: 30_000,
+        }
+    }
+}
+
+impl Timeouts {
+    /// A client for lookups, which gives up on any request that takes longer than `request_ms`.
+    pub fn lookup_client(&self) -> reqwest::Result<Client> {
+        self.builder()
+            .timeout(Duration::from_millis(self.request_ms))
+            .build()
+    }
+
+    /// A client for downloads, which only gives up if it can't connect.
+    pub fn download_client(&self) -> reqwest::Result<Client> {
+        self.builder().build()
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.read_ms)
+    }
+
+    fn builder(&self) -> ClientBuilder {
+        Client::builder()
+            .user_agent(USER_AGENT)
+            .referer(false)
+            .connect_timeout(Duration::from_millis(self.connect_ms))
+    }
+}
@@ -6,4 +6,6 @@ pub mod async_runtime;
 pub mod authentication;
 pub mod downloader;
 pub mod gamejoin;
+pub mod http;
 pub mod manifest;
+pub mod wine;
@@ -1,12 +1,33 @@
-use std::{fs, path::Path, process, thread};
+use std::{collections::HashMap, env, fs, path::Path, process, thread};
 
 use anyhow::Context;
+use deploy_history::{domain::Channel, endpoints::Endpoints, retry::RetryPolicy};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    downloader::{ExtractLimits, DEFAULT_RETAINED_VERSIONS},
+    http::Timeouts,
+    wine::WineConfig,
+};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProjectManifest {
     pub game: GameConfig,
     pub design: DesignConfig,
+    /// Overrides for the hosts we talk to. Anything not overridden points at Roblox.
+    #[serde(default)]
+    pub endpoints: Endpoints,
+    #[serde(default)]
+    pub client: ClientConfig,
+    /// How requests to Roblox which fail for transient reasons are retried.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// How long requests to Roblox can take before giving up.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// How the Windows client is run on Linux.
+    #[serde(default)]
+    pub wine: WineConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -21,6 +42,44 @@ pub struct DesignConfig {
     pub height: u32,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientConfig {
+    /// SHA-256 checksums that downloaded client files must match, by file name. Useful for files Roblox doesn't
+    /// publish checksums for, such as `RobloxPlayer.zip` on Mac.
+    #[serde(default)]
+    pub checksums: HashMap<String, String>,
+    /// Keep players on this version instead of the latest one. Either a version GUID or a dotted version.
+    pub version: Option<String>,
+    /// The channel to install the latest version from, or to find `version` in. Defaults to `live`.
+    pub channel: Option<Channel>,
+    /// How many installed versions to keep to fall back on, including the current one.
+    #[serde(default = "default_retained_versions")]
+    pub retained_versions: usize,
+    /// Install the client in this app's own directory, instead of a store shared with other Packer games.
+    #[serde(default)]
+    pub portable: bool,
+    /// Limits on what each downloaded package can extract to.
+    #[serde(default)]
+    pub extract: ExtractLimits,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            checksums: HashMap::new(),
+            version: None,
+            channel: None,
+            retained_versions: DEFAULT_RETAINED_VERSIONS,
+            portable: false,
+            extract: ExtractLimits::default(),
+        }
+    }
+}
+
+fn default_retained_versions() -> usize {
+    DEFAULT_RETAINED_VERSIONS
+}
+
 impl ProjectManifest {
     pub fn get(root_dir: &Path) -> anyhow::Result<ProjectManifest> {
         let manifest_path = root_dir.join("manifest.toml");
@@ -34,6 +93,42 @@ impl ProjectManifest {
 
         Ok(manifest)
     }
+
+    /// Endpoints from the manifest, with any overrides from the environment applied on top. Useful for pointing an
+    /// already built launcher at a local stand-in server.
+    pub fn endpoints(&self) -> Endpoints {
+        let mut endpoints = self.endpoints.clone();
+
+        let overrides = [
+            ("PACKER_CLIENTSETTINGS_URL", &mut endpoints.clientsettings),
+            ("PACKER_SETUP_URL", &mut endpoints.setup),
+            ("PACKER_AUTH_URL", &mut endpoints.auth),
+            ("PACKER_ASSETGAME_URL", &mut endpoints.assetgame),
+            ("PACKER_WWW_URL", &mut endpoints.www),
+        ];
+
+        for (variable, endpoint) in overrides {
+            if let Ok(url) = env::var(variable) {
+                log::info!("Overriding endpoint with {variable}: {url}");
+                *endpoint = url;
+            }
+        }
+
+        endpoints.normalized()
+    }
+
+    /// Wine config from the manifest, with the Wine binary overridden by `PACKER_WINE` if it's set. Players' Wine
+    /// installs vary a lot more than the endpoints do.
+    pub fn wine(&self) -> WineConfig {
+        let mut wine = self.wine.clone();
+
+        if let Some(binary) = env::var_os("PACKER_WINE") {
+            log::info!("Overriding Wine binary with PACKER_WINE: {binary:?}");
+            wine.binary = binary.into();
+        }
+
+        wine
+    }
 }
 
 impl Drop for ProjectManifest {
@@ -0,0 +1,147 @@
+//! Running the Windows client through Wine, on OSes Roblox doesn't make a client for (i.e. Linux).
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::downloader::InstalledClient;
+
+/// Where the app's Wine prefix is kept, relative to the root directory.
+pub const PREFIX_DIR: &str = "wine";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WineConfig {
+    /// The Wine binary to run the client with, either a path or a name to look up in `PATH`.
+    #[serde(default = "default_binary")]
+    pub binary: PathBuf,
+}
+
+impl Default for WineConfig {
+    fn default() -> Self {
+        Self {
+            binary: default_binary(),
+        }
+    }
+}
+
+fn default_binary() -> PathBuf {
+    PathBuf::from("wine")
+}
+
+/// A Wine binary and the private prefix the client runs in, so it's kept apart from anything else the player runs
+/// through Wine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wine {
+    binary: PathBuf,
+    prefix: PathBuf,
+}
+
+impl Wine {
+    pub fn new(root_dir: &Path, config: &WineConfig) -> Self {
+        Self {
+            binary: config.binary.to_owned(),
+            prefix: root_dir.join(PREFIX_DIR),
+        }
+    }
+
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Create the prefix if it hasn't been already. This takes a while the first time.
+    pub fn prepare_prefix(&self) -> anyhow::Result<()> {
+        // Wine writes this once a prefix has been set up.
+        if self.prefix.join("system.reg").exists() {
+            return Ok(());
+        }
+
+        log::info!("Creating Wine prefix at {:?}", self.prefix);
+
+        let status = self
+            .command("wineboot")
+            .arg("--init")
+            .status()
+            .context(format!(
+                "Failed to run Wine ({:?}), is it installed?",
+                self.binary
+            ))?;
+
+        if !status.success() {
+            bail!(
+                "Failed to create Wine prefix at {:?}: {status}",
+                self.prefix
+            );
+        }
+
+        Ok(())
+    }
+
+    /// A command that runs `program` in the prefix.
+    pub fn command(&self, program: impl AsRef<Path>) -> Command {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg(program.as_ref())
+            .envs(self.env())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .stdin(Stdio::null());
+
+        command
+    }
+
+    /// Run `client` through Wine, in the prefix.
+    pub fn wrap(&self, mut client: InstalledClient) -> InstalledClient {
+        client.runner = Some(self.binary.to_owned());
+        client.env.extend(self.env());
+        client
+    }
+
+    fn env(&self) -> [(String, OsString); 2] {
+        [
+            ("WINEPREFIX".into(), self.prefix.clone().into()),
+            // Wine is very noisy otherwise.
+            ("WINEDEBUG".into(), "-all".into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, path::Path};
+
+    use crate::{
+        downloader::InstalledClient,
+        wine::{Wine, WineConfig},
+    };
+
+    #[test]
+    fn runs_programs_in_private_prefix() {
+        let config = WineConfig {
+            binary: "/opt/wine/bin/wine64".into(),
+        };
+        let wine = Wine::new(Path::new("/games/wild-west"), &config);
+        assert_eq!(wine.prefix(), Path::new("/games/wild-west/wine"));
+
+        let client = InstalledClient::new(
+            Path::new("/games/wild-west/client/version-a"),
+            "RobloxPlayerBeta.exe",
+        );
+        let command = wine.wrap(client).command();
+        assert_eq!(command.get_program(), "/opt/wine/bin/wine64");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["/games/wild-west/client/version-a/RobloxPlayerBeta.exe"]
+        );
+        assert_eq!(
+            command.get_current_dir(),
+            Some(Path::new("/games/wild-west/client/version-a"))
+        );
+        assert!(command.get_envs().any(|(key, value)| key == "WINEPREFIX"
+            && value == Some(OsStr::new("/games/wild-west/wine"))));
+    }
+}
@@ -0,0 +1,1101 @@
+//! Drives the bootstrap sequence against a local mock of Roblox's services, so none of these tests touch the
+//! network.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use client_bootstrapper::{
+    async_runtime::{update_client, Message},
+    authentication::AuthenticationContext,
+    downloader::{verify_client, DownloadContext, SharedStore, UpdateLock, VersionCheckFailure},
+    gamejoin::GamejoinContext,
+    http::Timeouts,
+};
+use crossbeam::channel::{self, Receiver};
+use deploy_history::{
+    domain::{BinaryType, Channel},
+    endpoints::Endpoints,
+    retry::{FakeClock, RetryPolicy},
+};
+use mock_roblox::{zip_package, MockRoblox, AUTHENTICATION_TICKET, CSRF_TOKEN, ROBLOSECURITY};
+use reqwest::{header::RANGE, Method, StatusCode};
+use tempfile::TempDir;
+use tokio::time::sleep;
+
+const PLAYER_PATH: &str = "RobloxPlayer.app/Contents/MacOS/RobloxPlayer";
+
+/// Where the player of an installed Mac client is.
+fn player_path(root_dir: &Path, version_guid: &str) -> PathBuf {
+    root_dir.join("client").join(version_guid).join(PLAYER_PATH)
+}
+
+/// Publish a Mac client on the mock, whose player binary contains `contents`.
+fn publish_mac_client(mock: &MockRoblox, version: &str, version_guid: &str, contents: &[u8]) {
+    mock.set_client_version("MacPlayer", "live", version, version_guid);
+    mock.add_file(
+        &format!("/mac/{version_guid}-RobloxPlayer.zip"),
+        zip_package(&[
+            ("RobloxPlayer.app/", b""),
+            ("RobloxPlayer.app/Contents/MacOS/RobloxPlayer", contents),
+            ("RobloxPlayer.app/Contents/Info.plist", b"<plist/>"),
+        ]),
+    );
+}
+
+async fn download_context(mock: &MockRoblox, root_dir: &Path) -> DownloadContext {
+    download_context_with_endpoints(root_dir, mock.endpoints()).await
+}
+
+/// A context for the Mac client, built under the update lock like a launcher would.
+async fn download_context_with_endpoints(root_dir: &Path, endpoints: Endpoints) -> DownloadContext {
+    let update_lock =
+        UpdateLock::acquire(root_dir, None, || panic!("No other launcher is updating"))
+            .await
+            .unwrap();
+
+    DownloadContext::with_binary_type(root_dir, endpoints, BinaryType::MacPlayer, &update_lock)
+        .unwrap()
+}
+
+/// Every task sent to the UI, skipping download progress.
+fn messages(receiver: &Receiver<Message>) -> Vec<Message> {
+    receiver
+        .try_iter()
+        .filter(|message| !matches!(message, Message::DownloadProgress(_)))
+        .collect()
+}
+
+/// Authentication is read from the environment by `rbx_cookie`. Every test uses the same cookie, so it's fine that
+/// this is shared between tests.
+fn authenticate() {
+    env::set_var("ROBLOSECURITY", ROBLOSECURITY);
+}
+
+#[tokio::test]
+async fn installs_missing_client() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    // A download of an older version which was never finished.
+    let stale_download = root_dir.path().join("downloads/version-0000000000000000");
+    fs::create_dir_all(&stale_download).unwrap();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(fs::read(&player).unwrap(), b"player");
+
+    let installed_client = context.installed_client(root_dir.path()).unwrap();
+    assert_eq!(
+        installed_client.working_dir,
+        root_dir.path().join("client/version-d780cbcde4ab4f52")
+    );
+    assert!(installed_client.runner.is_none());
+    assert_eq!(
+        messages(&receiver),
+        [Message::CheckingForUpdates, Message::DownloadingClient]
+    );
+    assert!(!stale_download.exists());
+    assert!(!root_dir
+        .path()
+        .join("downloads/version-d780cbcde4ab4f52")
+        .exists());
+
+    let lock = fs::read_to_string(root_dir.path().join("client/client.lock")).unwrap();
+    assert!(lock.contains("version-d780cbcde4ab4f52"));
+
+    // The package is sized up with a HEAD request, then downloaded in ranged chunks.
+    let package_requests: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|request| request.path.starts_with("/mac/"))
+        .collect();
+    assert_eq!(package_requests[0].method, Method::HEAD);
+    assert!(package_requests.len() > 2);
+    for request in &package_requests[1..] {
+        assert_eq!(request.method, Method::GET);
+        assert!(request.headers.contains_key(RANGE));
+    }
+}
+
+#[tokio::test]
+async fn reports_download_progress() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        &[7; 50_000],
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    let progress: Vec<_> = receiver
+        .try_iter()
+        .filter_map(|message| match message {
+            Message::DownloadProgress(progress) => Some(progress),
+            _ => None,
+        })
+        .collect();
+
+    assert!(!progress.is_empty());
+    assert!(progress
+        .windows(2)
+        .all(|pair| pair[0].bytes_received <= pair[1].bytes_received));
+
+    // The last report is sent once the download is finished.
+    let last = progress.last().unwrap();
+    assert_eq!(last.file, "version-d780cbcde4ab4f52-RobloxPlayer.zip");
+    assert!(last.total_bytes > 0);
+    assert_eq!(last.bytes_received, last.total_bytes);
+}
+
+#[tokio::test]
+async fn skips_download_when_up_to_date() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    messages(&receiver);
+
+    let requests_before = mock.requests().len();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    assert!(!context.require_client_download().await.unwrap());
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    assert_eq!(messages(&receiver), [Message::CheckingForUpdates]);
+    assert!(mock.requests()[requests_before..]
+        .iter()
+        .all(|request| !request.path.starts_with("/mac/")));
+}
+
+#[tokio::test]
+async fn updates_outdated_client() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    publish_mac_client(&mock, "0.554.0.5540123", "version-0a1b2c3d4e5f6a7b", b"new");
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    assert!(context.require_client_download().await.unwrap());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(fs::read(player).unwrap(), b"new");
+    let lock = fs::read_to_string(root_dir.path().join("client/client.lock")).unwrap();
+    assert!(lock.contains("version-0a1b2c3d4e5f6a7b"));
+
+    // The old client is kept to fall back on.
+    let old_player = player_path(root_dir.path(), "version-d780cbcde4ab4f52");
+    assert_eq!(fs::read(old_player).unwrap(), b"old");
+    assert!(!root_dir
+        .path()
+        .join("client/version-0a1b2c3d4e5f6a7b.staging")
+        .exists());
+}
+
+#[tokio::test]
+async fn rolls_back_broken_update() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    // An update which has the app bundle, but no player binary inside it.
+    mock.set_client_version(
+        "MacPlayer",
+        "live",
+        "0.554.0.5540123",
+        "version-0a1b2c3d4e5f6a7b",
+    );
+    mock.add_file(
+        "/mac/version-0a1b2c3d4e5f6a7b-RobloxPlayer.zip",
+        zip_package(&[("RobloxPlayer.app/", b"")]),
+    );
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(fs::read(player).unwrap(), b"old");
+    let lock = fs::read_to_string(root_dir.path().join("client/client.lock")).unwrap();
+    assert!(lock.contains("version-d780cbcde4ab4f52"));
+    // Only remembered as broken, not installed.
+    assert!(!lock.contains("version_guid = \"version-0a1b2c3d4e5f6a7b\""));
+    assert!(lock.contains("broken_versions"));
+    assert!(!root_dir
+        .path()
+        .join("client/version-0a1b2c3d4e5f6a7b")
+        .exists());
+}
+
+#[tokio::test]
+async fn keeps_rolled_back_update_uninstalled() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    mock.set_client_version(
+        "MacPlayer",
+        "live",
+        "0.554.0.5540123",
+        "version-0a1b2c3d4e5f6a7b",
+    );
+    mock.add_file(
+        "/mac/version-0a1b2c3d4e5f6a7b-RobloxPlayer.zip",
+        zip_package(&[("RobloxPlayer.app/", b"")]),
+    );
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    let requests = package_requests(&mock);
+
+    for _ in 0..2 {
+        let mut context = download_context(&mock, root_dir.path()).await;
+        let player = update_client(root_dir.path(), &mut context, &sender)
+            .await
+            .unwrap()
+            .executable;
+
+        assert_eq!(fs::read(player).unwrap(), b"old");
+    }
+    assert_eq!(package_requests(&mock), requests);
+
+    // A fixed update is installed as usual.
+    publish_mac_client(&mock, "0.554.0.5540200", "version-1b2c3d4e5f6a7b8c", b"new");
+    let mut context = download_context(&mock, root_dir.path()).await;
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(fs::read(player).unwrap(), b"new");
+    let lock = fs::read_to_string(root_dir.path().join("client/client.lock")).unwrap();
+    assert!(!lock.contains("broken_versions"));
+}
+
+#[tokio::test]
+async fn verifies_installed_client() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert!(verify_client(root_dir.path()).unwrap().is_intact());
+
+    fs::write(&player, b"tampered").unwrap();
+    assert_eq!(verify_client(root_dir.path()).unwrap().modified, [player]);
+}
+
+#[tokio::test]
+async fn rejects_client_with_wrong_checksum() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+
+    publish_mac_client(&mock, "0.554.0.5540123", "version-0a1b2c3d4e5f6a7b", b"new");
+
+    let checksums = HashMap::from([(
+        "version-0a1b2c3d4e5f6a7b-RobloxPlayer.zip".to_owned(),
+        "0".repeat(64),
+    )]);
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_checksums(checksums);
+    assert!(update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .is_err());
+
+    // The installed client is left alone.
+    let old_player = player_path(root_dir.path(), "version-d780cbcde4ab4f52");
+    assert_eq!(fs::read(old_player).unwrap(), b"old");
+    assert!(!root_dir
+        .path()
+        .join("client/version-0a1b2c3d4e5f6a7b")
+        .exists());
+}
+
+/// How many times a Mac client package has been requested.
+fn package_requests(mock: &MockRoblox) -> usize {
+    mock.requests()
+        .into_iter()
+        .filter(|request| request.path.starts_with("/mac/"))
+        .count()
+}
+
+#[tokio::test]
+async fn shares_clients_between_apps() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    let store_dir = TempDir::new().unwrap();
+    let first_app = TempDir::new().unwrap();
+    let second_app = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let shared = |context: DownloadContext| {
+        context
+            .with_shared_store(Some(SharedStore::new(store_dir.path())))
+            .with_retained_versions(1)
+    };
+
+    let mut context = shared(download_context(&mock, first_app.path()).await);
+    let first_player = update_client(first_app.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    let downloaded = package_requests(&mock);
+
+    // The second app uses the client the first one downloaded.
+    let mut context = shared(download_context(&mock, second_app.path()).await);
+    let second_player = update_client(second_app.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(second_player, first_player);
+    assert!(first_player.starts_with(store_dir.path().join("MacPlayer")));
+    assert_eq!(package_requests(&mock), downloaded);
+
+    // The old version is kept until neither app uses it.
+    publish_mac_client(&mock, "0.554.0.5540123", "version-0a1b2c3d4e5f6a7b", b"new");
+
+    let mut context = shared(download_context(&mock, first_app.path()).await);
+    update_client(first_app.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    assert_eq!(fs::read(&first_player).unwrap(), b"old");
+
+    let mut context = shared(download_context(&mock, second_app.path()).await);
+    let player = update_client(second_app.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(fs::read(player).unwrap(), b"new");
+    assert!(!first_player.exists());
+}
+
+#[tokio::test]
+async fn recovers_interrupted_install_in_shared_store() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let store_dir = TempDir::new().unwrap();
+    let shared_store = SharedStore::new(store_dir.path());
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_shared_store(Some(shared_store.clone()));
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    // A launcher was closed part way through installing one version, and just after moving another into the store.
+    let versions_dir = store_dir.path().join("MacPlayer");
+    fs::create_dir_all(versions_dir.join("version-0a1b2c3d4e5f6a7b.staging")).unwrap();
+    fs::create_dir_all(versions_dir.join("version-1b2c3d4e5f6a7b8c")).unwrap();
+
+    let update_lock = UpdateLock::acquire(root_dir.path(), Some(&shared_store), || {
+        panic!("No other launcher is updating")
+    })
+    .await
+    .unwrap();
+    let mut context = DownloadContext::with_binary_type(
+        root_dir.path(),
+        mock.endpoints(),
+        BinaryType::MacPlayer,
+        &update_lock,
+    )
+    .unwrap()
+    .with_shared_store(Some(shared_store.clone()));
+    context.recover(root_dir.path(), &update_lock).unwrap();
+
+    assert!(!versions_dir
+        .join("version-0a1b2c3d4e5f6a7b.staging")
+        .exists());
+    assert!(!versions_dir.join("version-1b2c3d4e5f6a7b8c").exists());
+
+    let recovered_player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(recovered_player, player);
+    assert_eq!(fs::read(player).unwrap(), b"player");
+}
+
+#[tokio::test]
+async fn opts_out_of_shared_store() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let store_dir = TempDir::new().unwrap();
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_shared_store(Some(SharedStore::new(store_dir.path())));
+    let shared_player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    // Going portable installs the client again, and lets go of the shared one.
+    let mut context = download_context(&mock, root_dir.path()).await;
+    assert!(context.require_client_download().await.unwrap());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert!(!shared_player.exists());
+}
+
+/// Install `version_guid`, then lose the cached clientsettings responses so the next version check has to go to the
+/// network.
+async fn install_then_clear_cache(mock: &MockRoblox, root_dir: &Path) {
+    let (sender, _receiver) = channel::unbounded();
+    let mut context = download_context(mock, root_dir).await;
+    update_client(root_dir, &mut context, &sender)
+        .await
+        .unwrap();
+
+    fs::remove_dir_all(root_dir.join("cache")).unwrap();
+}
+
+#[tokio::test]
+async fn falls_back_to_installed_client_on_server_error() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    install_then_clear_cache(&mock, root_dir.path()).await;
+
+    mock.fail_requests(
+        "/v2/client-version/MacPlayer/channel/live",
+        StatusCode::INTERNAL_SERVER_ERROR,
+        usize::MAX,
+    );
+
+    let (sender, receiver) = channel::unbounded();
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_retry_policy(RetryPolicy::none());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(
+        context.version_check_failure(),
+        Some(VersionCheckFailure::ServerError)
+    );
+    assert_eq!(
+        messages(&receiver),
+        [
+            Message::CheckingForUpdates,
+            Message::VersionCheckFailed(VersionCheckFailure::ServerError)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_installed_client_without_network() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    install_then_clear_cache(&mock, root_dir.path()).await;
+
+    // Nothing listens on port 1.
+    let mut endpoints = mock.endpoints();
+    endpoints.clientsettings = "http://127.0.0.1:1".to_owned();
+
+    let (sender, _receiver) = channel::unbounded();
+    let mut context = download_context_with_endpoints(root_dir.path(), endpoints)
+        .await
+        .with_retry_policy(RetryPolicy::none());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(
+        context.version_check_failure(),
+        Some(VersionCheckFailure::NoNetwork)
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_installed_client_when_version_check_stalls() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    install_then_clear_cache(&mock, root_dir.path()).await;
+
+    mock.stall_requests("/v2/client-version/MacPlayer/channel/live", usize::MAX);
+
+    let (sender, _receiver) = channel::unbounded();
+    let timeouts = Timeouts {
+        request_ms: 200,
+        ..Default::default()
+    };
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_retry_policy(RetryPolicy::none())
+        .with_timeouts(timeouts)
+        .unwrap();
+    let player = tokio::time::timeout(
+        Duration::from_secs(10),
+        update_client(root_dir.path(), &mut context, &sender),
+    )
+    .await
+    .expect("the version check should time out")
+    .unwrap()
+    .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(
+        context.version_check_failure(),
+        Some(VersionCheckFailure::NoNetwork)
+    );
+}
+
+#[tokio::test]
+async fn warns_when_cache_answers_for_server_error() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    messages(&receiver);
+
+    // The cached clientsettings response is served instead.
+    mock.fail_requests(
+        "/v2/client-version/MacPlayer/channel/live",
+        StatusCode::INTERNAL_SERVER_ERROR,
+        usize::MAX,
+    );
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_retry_policy(RetryPolicy::none());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(
+        context.version_check_failure(),
+        Some(VersionCheckFailure::ServerError)
+    );
+    assert_eq!(
+        messages(&receiver),
+        [
+            Message::CheckingForUpdates,
+            Message::VersionCheckFailed(VersionCheckFailure::ServerError)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn warns_when_cache_answers_without_network() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let endpoints = mock.endpoints();
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    messages(&receiver);
+
+    // Roblox goes away, but the cached clientsettings response is still there.
+    drop(mock);
+    sleep(Duration::from_millis(100)).await;
+
+    let mut context = download_context_with_endpoints(root_dir.path(), endpoints)
+        .await
+        .with_retry_policy(RetryPolicy::none());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(
+        context.version_check_failure(),
+        Some(VersionCheckFailure::NoNetwork)
+    );
+    assert_eq!(
+        messages(&receiver),
+        [
+            Message::CheckingForUpdates,
+            Message::VersionCheckFailed(VersionCheckFailure::NoNetwork)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn errors_when_version_check_fails_without_installed_client() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    mock.fail_requests(
+        "/v2/client-version/MacPlayer/channel/live",
+        StatusCode::INTERNAL_SERVER_ERROR,
+        usize::MAX,
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_retry_policy(RetryPolicy::none());
+    assert!(update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .is_err());
+    assert!(context.version_check_failure().is_none());
+}
+
+#[tokio::test]
+async fn reinstalls_over_corrupt_lock() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let lock_path = root_dir.path().join("client/client.lock");
+    fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+    fs::write(&lock_path, "current = ").unwrap();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    assert!(context.installed_client_dir(root_dir.path()).is_none());
+    assert_eq!(
+        fs::read_to_string(root_dir.path().join("client/client.lock.corrupt")).unwrap(),
+        "current = "
+    );
+
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(fs::read(player).unwrap(), b"player");
+
+    let lock = fs::read_to_string(&lock_path).unwrap();
+    assert!(lock.contains("schema_version = 2"));
+    assert!(lock.contains("/mac/version-d780cbcde4ab4f52-RobloxPlayer.zip"));
+    assert!(verify_client(root_dir.path()).unwrap().is_intact());
+}
+
+#[tokio::test]
+async fn errors_without_published_client() {
+    let mock = MockRoblox::start();
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    assert!(update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .is_err());
+    assert!(context.installed_client_dir(root_dir.path()).is_none());
+}
+
+#[tokio::test]
+async fn pins_client_version() {
+    let mock = MockRoblox::start();
+    publish_mac_client(&mock, "0.553.0.5530470", "version-d780cbcde4ab4f52", b"old");
+    publish_mac_client(&mock, "0.554.0.5540123", "version-0a1b2c3d4e5f6a7b", b"new");
+    mock.add_file(
+        "/DeployHistory.txt",
+        "New MacPlayer version-d780cbcde4ab4f52 at 11/9/2022 2:10:18 PM, file version: 0, 553, 0, 5530470, git hash: 6c7a4d5b ...\r\n\
+         New MacPlayer version-0a1b2c3d4e5f6a7b at 11/16/2022 1:42:07 PM, file version: 0, 554, 0, 5540123, git hash: 9e8f7a6b ...\r\n",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path()).await;
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(fs::read(player).unwrap(), b"new");
+
+    // Pinned by its dotted version, which is looked up in the deploy history.
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_pinned_version(Some("0.553.0.5530470".into()));
+    assert!(context.require_client_download().await.unwrap());
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(fs::read(player).unwrap(), b"old");
+
+    // Pinning a version that's already installed doesn't download it again.
+    let before = package_requests(&mock);
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_pinned_version(Some("version-0a1b2c3d4e5f6a7b".into()));
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+    assert_eq!(fs::read(player).unwrap(), b"new");
+    assert_eq!(package_requests(&mock), before);
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_pinned_version(Some("0.400.0.4000000".into()));
+    assert!(update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn follows_channel() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"live",
+    );
+    mock.set_client_version(
+        "MacPlayer",
+        "zcanary",
+        "0.554.0.5540123",
+        "version-0a1b2c3d4e5f6a7b",
+    );
+    mock.add_file(
+        "/channel/zcanary/mac/version-0a1b2c3d4e5f6a7b-RobloxPlayer.zip",
+        zip_package(&[
+            ("RobloxPlayer.app/", b""),
+            ("RobloxPlayer.app/Contents/MacOS/RobloxPlayer", b"canary"),
+        ]),
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let mut context = download_context(&mock, root_dir.path())
+        .await
+        .with_channel(Channel::ZCanary);
+    let player = update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap()
+        .executable;
+
+    assert_eq!(fs::read(player).unwrap(), b"canary");
+}
+
+#[tokio::test]
+async fn prunes_old_versions() {
+    let mock = MockRoblox::start();
+    let root_dir = TempDir::new().unwrap();
+    let (sender, _receiver) = channel::unbounded();
+
+    let versions = [
+        ("0.553.0.5530470", "version-d780cbcde4ab4f52"),
+        ("0.554.0.5540123", "version-0a1b2c3d4e5f6a7b"),
+        ("0.555.0.5550001", "version-1b2c3d4e5f6a7b8c"),
+    ];
+    for (version, version_guid) in versions {
+        publish_mac_client(&mock, version, version_guid, version_guid.as_bytes());
+
+        let mut context = download_context(&mock, root_dir.path())
+            .await
+            .with_retained_versions(2);
+        update_client(root_dir.path(), &mut context, &sender)
+            .await
+            .unwrap();
+    }
+
+    let client_dir = root_dir.path().join("client");
+    assert!(!client_dir.join("version-d780cbcde4ab4f52").exists());
+    assert!(client_dir.join("version-0a1b2c3d4e5f6a7b").exists());
+    assert!(client_dir.join("version-1b2c3d4e5f6a7b8c").exists());
+
+    let lock = fs::read_to_string(client_dir.join("client.lock")).unwrap();
+    assert!(!lock.contains("version-d780cbcde4ab4f52"));
+}
+
+#[tokio::test]
+async fn waits_for_other_launcher_to_update() {
+    let mock = MockRoblox::start();
+    publish_mac_client(
+        &mock,
+        "0.553.0.5530470",
+        "version-d780cbcde4ab4f52",
+        b"player",
+    );
+    let root_dir = TempDir::new().unwrap();
+    let (sender, receiver) = channel::unbounded();
+
+    let update_lock = UpdateLock::acquire(root_dir.path(), None, || {
+        panic!("No other launcher is updating")
+    })
+    .await
+    .unwrap();
+
+    // A second launcher, started while the first is updating.
+    let second_launcher = tokio::spawn({
+        let root_dir = root_dir.path().to_owned();
+        let endpoints = mock.endpoints();
+        let sender = sender.clone();
+        async move {
+            let update_lock = UpdateLock::acquire(&root_dir, None, || {
+                sender.send(Message::WaitingForOtherLauncher).unwrap()
+            })
+            .await
+            .unwrap();
+
+            let mut context = DownloadContext::with_binary_type(
+                &root_dir,
+                endpoints,
+                BinaryType::MacPlayer,
+                &update_lock,
+            )
+            .unwrap();
+            update_client(&root_dir, &mut context, &sender)
+                .await
+                .unwrap()
+                .executable
+        }
+    });
+
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(messages(&receiver), [Message::WaitingForOtherLauncher]);
+
+    let mut context = DownloadContext::with_binary_type(
+        root_dir.path(),
+        mock.endpoints(),
+        BinaryType::MacPlayer,
+        &update_lock,
+    )
+    .unwrap();
+    update_client(root_dir.path(), &mut context, &sender)
+        .await
+        .unwrap();
+    messages(&receiver);
+
+    let requests_before = mock.requests().len();
+    drop(update_lock);
+
+    // The second launcher uses the client the first installed, rather than downloading it again.
+    let player = second_launcher.await.unwrap();
+    assert_eq!(
+        player,
+        player_path(root_dir.path(), "version-d780cbcde4ab4f52")
+    );
+    assert_eq!(messages(&receiver), [Message::CheckingForUpdates]);
+    assert!(mock.requests()[requests_before..]
+        .iter()
+        .all(|request| !request.path.starts_with("/mac/")));
+}
+
+#[tokio::test]
+async fn creates_authentication_ticket() {
+    authenticate();
+    let mock = MockRoblox::start();
+    let auth_context = AuthenticationContext::new();
+    let gamejoin_context = GamejoinContext::new(&auth_context, mock.endpoints()).unwrap();
+
+    let ticket = gamejoin_context
+        .create_authentication_ticket()
+        .await
+        .unwrap();
+    assert_eq!(ticket, AUTHENTICATION_TICKET);
+
+    // The X-CSRF token from the logout request is passed along when asking for a ticket.
+    let requests = mock.requests();
+    assert_eq!(requests[0].path, "/v2/logout");
+    assert_eq!(requests[1].path, "/v1/authentication-ticket");
+    assert_eq!(requests[1].headers["x-csrf-token"], CSRF_TOKEN);
+}
+
+#[tokio::test]
+async fn retries_authentication_ticket() {
+    authenticate();
+    let mock = MockRoblox::start();
+    mock.fail_requests("/v1/authentication-ticket", StatusCode::BAD_GATEWAY, 2);
+
+    let clock = Arc::new(FakeClock::new(SystemTime::UNIX_EPOCH));
+    let auth_context = AuthenticationContext::new();
+    let gamejoin_context = GamejoinContext::new(&auth_context, mock.endpoints())
+        .unwrap()
+        .with_retry_policy(RetryPolicy::default().with_clock(clock.clone()));
+
+    let ticket = gamejoin_context
+        .create_authentication_ticket()
+        .await
+        .unwrap();
+    assert_eq!(ticket, AUTHENTICATION_TICKET);
+    assert_eq!(clock.sleeps().len(), 2);
+}
+
+#[tokio::test]
+async fn generates_application_args() {
+    authenticate();
+    let mock = MockRoblox::start();
+    let auth_context = AuthenticationContext::new();
+    let gamejoin_context = GamejoinContext::new(&auth_context, mock.endpoints()).unwrap();
+
+    let args = gamejoin_context
+        .generate_application_args(&1818)
+        .await
+        .unwrap();
+
+    assert_eq!(args[0], "-ticket");
+    assert_eq!(args[1], AUTHENTICATION_TICKET);
+    assert_eq!(args[2], "-scriptURL");
+    assert!(args[3].starts_with(&format!("{}/game/PlaceLauncher.ashx?", mock.url())));
+    assert!(args[3].contains("placeId=1818"));
+    assert!(args[3].contains(&format!("browserTrackerId={}", args[5])));
+}
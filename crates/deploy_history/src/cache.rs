@@ -0,0 +1,376 @@
+//! Opt-in on-disk cache for responses from Roblox. Cached responses are revalidated with conditional requests, so
+//! unchanged responses (such as the multi-megabyte `DeployHistory.txt`) aren't downloaded again. If Roblox can't be
+//! reached, the cached response is served instead. Failed requests are retried before falling back.
+//!
+//! Serving a stale response is recorded, so callers can tell the answer may be out of date (see
+//! [`HttpCache::take_stale`]).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{retry::RetryPolicy, Error, Result};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+/// Why a cached response was served instead of a fresh one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    /// Roblox couldn't be reached, most likely because there's no internet connection.
+    NoNetwork,
+    /// Roblox was reached, but answered with a server error.
+    ServerError,
+}
+
+impl Staleness {
+    /// Why a request which got no response at all failed. A connection which can't be made or times out is most
+    /// likely down to our network, not Roblox.
+    pub fn from_error(error: &reqwest::Error) -> Self {
+        if error.is_connect() || error.is_timeout() {
+            Staleness::NoNetwork
+        } else {
+            Staleness::ServerError
+        }
+    }
+}
+
+/// A cached response served because Roblox didn't answer with a fresh one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleResponse {
+    pub url: String,
+    pub staleness: Staleness,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+    /// Stale responses served since `take_stale` was last called. Shared between clones.
+    stale: Arc<Mutex<Vec<StaleResponse>>>,
+}
+
+impl HttpCache {
+    /// Create a cache which stores responses in `dir`. The directory is created when the first response is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            stale: Arc::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Every stale response served since this was last called, oldest first.
+    pub fn take_stale(&self) -> Vec<StaleResponse> {
+        std::mem::take(&mut *self.stale.lock().unwrap())
+    }
+
+    /// GET `url`, revalidating any cached response. Falls back to the cached response if the request fails or
+    /// Roblox returns a server error, and records that it did.
+    pub(crate) async fn get(
+        &self,
+        client: &Client,
+        retry: &RetryPolicy,
+        url: &str,
+    ) -> Result<String> {
+        let entry = self.load(url);
+
+        let request = || {
+            let request = client.get(url);
+            match &entry {
+                Some(entry) => conditional(request, entry),
+                None => request,
+            }
+        };
+
+        let response = match retry.send(request).await {
+            Ok(response) => response,
+            Err(error) => {
+                let Some(entry) = entry else {
+                    return Err(Error::Network(error));
+                };
+
+                return Ok(self.serve_stale(url, entry, Staleness::from_error(&error)));
+            }
+        };
+
+        let status = response.status();
+        match (status, entry) {
+            (StatusCode::NOT_MODIFIED, Some(entry)) => Ok(entry.body),
+            (status, Some(entry)) if status.is_server_error() => {
+                Ok(self.serve_stale(url, entry, Staleness::ServerError))
+            }
+            (status, _) if status.is_success() => self.store(url, response).await,
+            (status, _) => Err(Error::UnexpectedStatus {
+                url: url.to_owned(),
+                status,
+            }),
+        }
+    }
+
+    fn serve_stale(&self, url: &str, entry: CacheEntry, staleness: Staleness) -> String {
+        self.stale.lock().unwrap().push(StaleResponse {
+            url: url.to_owned(),
+            staleness,
+        });
+
+        entry.body
+    }
+
+    fn path_for(&self, url: &str) -> PathBuf {
+        let name: String = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// A missing or unreadable entry is treated as a cache miss, it'll be overwritten by the next response.
+    fn load(&self, url: &str) -> Option<CacheEntry> {
+        let entry = fs::read_to_string(self.path_for(url)).ok()?;
+        let entry = serde_json::from_str::<CacheEntry>(&entry).ok()?;
+
+        // Different URLs could be sanitized into the same file name, so double check this is the right entry.
+        (entry.url == url).then_some(entry)
+    }
+
+    async fn store(&self, url: &str, response: Response) -> Result<String> {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = response.text().await?;
+
+        let entry = CacheEntry {
+            url: url.to_owned(),
+            etag,
+            last_modified,
+            body,
+        };
+
+        // Failing to write the cache shouldn't fail the request, we just won't have a cached response next time.
+        if fs::create_dir_all(&self.dir).is_ok() {
+            if let Ok(serialized) = serde_json::to_string(&entry) {
+                let _ = fs::write(self.path_for(url), serialized);
+            }
+        }
+
+        Ok(entry.body)
+    }
+}
+
+fn conditional(mut request: RequestBuilder, entry: &CacheEntry) -> RequestBuilder {
+    if let Some(etag) = &entry.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    if let Some(last_modified) = &entry.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    request
+}
+
+/// GET `url` as text, through the cache if there is one.
+pub(crate) async fn get_text(
+    client: &Client,
+    cache: Option<&HttpCache>,
+    retry: &RetryPolicy,
+    url: &str,
+) -> Result<String> {
+    if let Some(cache) = cache {
+        return cache.get(client, retry, url).await;
+    }
+
+    let response = retry.send(|| client.get(url)).await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::UnexpectedStatus {
+            url: url.to_owned(),
+            status,
+        });
+    }
+
+    Ok(response.text().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{mpsc, Arc},
+        thread::{self, JoinHandle},
+        time::SystemTime,
+    };
+
+    use reqwest::Client;
+
+    use crate::{
+        cache::{HttpCache, Staleness},
+        retry::{FakeClock, RetryPolicy},
+    };
+
+    /// Serves one canned response per connection, and reports back each request's headers. The server shuts down
+    /// once every response has been served.
+    fn serve(responses: Vec<&'static str>) -> (String, mpsc::Receiver<String>, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/DeployHistory.txt",
+            listener.local_addr().unwrap()
+        );
+        let (sender, receiver) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+
+                let mut request = String::new();
+                let mut reader = BufReader::new(&mut stream);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line.to_lowercase());
+                }
+
+                let _ = sender.send(request);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, receiver, handle)
+    }
+
+    fn get_cache(name: &str) -> HttpCache {
+        let dir = env::temp_dir().join(format!("deploy_history-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        HttpCache::new(dir)
+    }
+
+    /// Retries without waiting.
+    fn get_retry() -> RetryPolicy {
+        RetryPolicy::default().with_clock(Arc::new(FakeClock::new(SystemTime::UNIX_EPOCH)))
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_etag() {
+        let (url, requests, _) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 304 Not Modified\r\nETag: \"abc\"\r\nConnection: close\r\n\r\n",
+        ]);
+        let cache = get_cache("revalidates");
+        let client = Client::new();
+        let retry = get_retry();
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        assert!(!requests.recv().unwrap().contains("if-none-match"));
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        assert!(requests.recv().unwrap().contains("if-none-match: \"abc\""));
+
+        // An unchanged response is still fresh.
+        assert!(cache.take_stale().is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_stale_on_server_error() {
+        let (url, _, _) = serve(vec![
+            "HTTP/1.1 200 OK\r\nLast-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let cache = get_cache("stale");
+        let client = Client::new();
+        let retry = RetryPolicy::none();
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        assert!(cache.take_stale().is_empty());
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        let stale = cache.take_stale();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].url, url);
+        assert_eq!(stale[0].staleness, Staleness::ServerError);
+        assert!(cache.take_stale().is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_stale_when_offline() {
+        let (url, _, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+        ]);
+        let cache = get_cache("offline");
+        let client = Client::new();
+        let retry = get_retry();
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+
+        server.join().unwrap();
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        let stale = cache.take_stale();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].staleness, Staleness::NoNetwork);
+    }
+
+    #[tokio::test]
+    async fn errors_without_cached_response() {
+        let (url, _, _) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let cache = get_cache("uncached");
+        let client = Client::new();
+        let retry = get_retry();
+
+        assert!(cache.get(&client, &retry, &url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, requests, _) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+        ]);
+        let cache = get_cache("retries");
+        let client = Client::new();
+        let retry = get_retry();
+
+        assert_eq!(cache.get(&client, &retry, &url).await.unwrap(), "hello");
+        assert_eq!(requests.try_iter().count(), 3);
+    }
+}
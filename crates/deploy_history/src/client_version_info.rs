@@ -1,8 +1,16 @@
-use anyhow::Context;
+use std::{cmp::Ordering, fmt, str::FromStr};
+
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::{domain::BinaryType, domain::Channel};
+use crate::{
+    cache::{get_text, HttpCache},
+    domain::BinaryType,
+    domain::Channel,
+    endpoints::Endpoints,
+    retry::RetryPolicy,
+    Error, Result,
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,10 +31,10 @@ pub struct ClientVersionInfo {
 }
 
 impl ClientVersionInfo {
-    pub fn new(channel: Channel, version: String, version_guid: String) -> Self {
-        let (major_rev, version, patch, change_list) = parts_from_version(&version);
+    pub fn new(channel: Channel, version: String, version_guid: String) -> Result<Self> {
+        let (major_rev, version, patch, change_list) = parts_from_version(&version)?;
 
-        Self {
+        Ok(Self {
             channel,
             version_guid,
 
@@ -34,47 +42,166 @@ impl ClientVersionInfo {
             version,
             patch,
             change_list,
-        }
+        })
     }
 
+    /// Get the latest client version for a channel. Responses are cached in `cache` if one is given.
     pub async fn get(
         client: &Client,
+        endpoints: &Endpoints,
+        cache: Option<&HttpCache>,
+        retry: &RetryPolicy,
         channel: &Channel,
         binary_type: &BinaryType,
-    ) -> anyhow::Result<Self> {
-        let response = client
-            .get(format!(
-                "https://clientsettings.roblox.com/v2/client-version/{}/channel/{}",
-                binary_type.to_string(),
-                channel.to_string()
-            ))
-            .send()
-            .await
-            .context("Failed to send request for client version info")?
-            .json::<ClientVersionResponse>()
-            .await
-            .context("Failed to parse response for client version info into JSON")?;
-
-        let (major_rev, version, patch, change_list) = parts_from_version(&response.version);
+    ) -> Result<Self> {
+        let url = format!(
+            "{}/v2/client-version/{}/channel/{}",
+            endpoints.clientsettings,
+            binary_type.to_string(),
+            channel.to_string()
+        );
 
-        Ok(Self {
-            channel: channel.to_owned(),
-            version_guid: response.client_version_upload,
+        let response = get_text(client, cache, retry, &url).await?;
+        let response = serde_json::from_str::<ClientVersionResponse>(&response)?;
 
-            major_rev,
-            version,
-            patch,
-            change_list,
-        })
+        Self::new(
+            channel.to_owned(),
+            response.version,
+            response.client_version_upload,
+        )
+    }
+
+    pub fn is_newer_than(&self, other: &ClientVersionInfo) -> bool {
+        self > other
+    }
+
+    pub fn is_older_than(&self, other: &ClientVersionInfo) -> bool {
+        self < other
+    }
+
+    fn parts(&self) -> (usize, usize, usize, usize) {
+        (self.major_rev, self.version, self.patch, self.change_list)
     }
 }
 
-fn parts_from_version(version: &str) -> (usize, usize, usize, usize) {
-    let mut version_parts = version.split(".");
-    let major_rev = version_parts.nth(0).expect("major rev").parse().unwrap();
-    let version = version_parts.nth(0).expect("major rev").parse().unwrap();
-    let patch = version_parts.nth(0).expect("major rev").parse().unwrap();
-    let change_list = version_parts.nth(0).expect("major rev").parse().unwrap();
+/// Versions are compared by all four of their parts, so two infos with the same version are equal even if they
+/// came from different channels.
+impl Ord for ClientVersionInfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts().cmp(&other.parts())
+    }
+}
 
-    (major_rev, version, patch, change_list)
+impl PartialOrd for ClientVersionInfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ClientVersionInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts() == other.parts()
+    }
+}
+
+impl Eq for ClientVersionInfo {}
+
+/// Formats as the dotted version string, e.g. `0.553.0.5530470`.
+impl fmt::Display for ClientVersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major_rev, self.version, self.patch, self.change_list
+        )
+    }
+}
+
+/// Parses a dotted version string, e.g. `0.553.0.5530470`. The dotted form doesn't include a channel or version
+/// GUID, so these are set to [`Channel::Live`] and an empty string. This is mostly useful for comparing against a
+/// known version.
+impl FromStr for ClientVersionInfo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(Channel::Live, s.to_owned(), String::new())
+    }
+}
+
+/// Splits a version string such as `0.553.0.5530470` into its four parts.
+fn parts_from_version(version: &str) -> Result<(usize, usize, usize, usize)> {
+    let malformed = || Error::MalformedVersion(version.to_owned());
+
+    let parts = version
+        .split('.')
+        .map(|part| part.parse::<usize>().map_err(|_| malformed()))
+        .collect::<Result<Vec<_>>>()?;
+
+    match parts[..] {
+        [major_rev, version, patch, change_list] => Ok((major_rev, version, patch, change_list)),
+        _ => Err(malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        client_version_info::{parts_from_version, ClientVersionInfo},
+        domain::Channel,
+        Error,
+    };
+
+    #[test]
+    fn parses_version() {
+        let parts = parts_from_version("0.553.0.5530470").unwrap();
+        assert_eq!(parts, (0, 553, 0, 5530470));
+    }
+
+    #[test]
+    fn rejects_malformed_versions() {
+        for version in ["0.553.0", "0.553.0.5530470.1", "0.553.x.5530470", ""] {
+            let result = parts_from_version(version);
+            assert!(
+                matches!(result, Err(Error::MalformedVersion(_))),
+                "{version:?} should be malformed"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_display() {
+        let version = ClientVersionInfo::from_str("0.553.0.5530470").unwrap();
+        assert_eq!(version.to_string(), "0.553.0.5530470");
+    }
+
+    #[test]
+    fn orders_by_every_part() {
+        let version = |s| ClientVersionInfo::from_str(s).unwrap();
+
+        assert!(version("0.553.0.5530470") < version("0.553.0.5530471"));
+        assert!(version("0.553.1.5530470") > version("0.553.0.5530471"));
+        assert!(version("0.554.0.0") > version("0.553.9.9999999"));
+        assert!(version("1.0.0.0") > version("0.999.0.0"));
+    }
+
+    #[test]
+    fn compares_versions() {
+        let installed = ClientVersionInfo::from_str("0.553.0.5530470").unwrap();
+        let latest = ClientVersionInfo::new(
+            Channel::ZCanary,
+            "0.554.0.5540123".into(),
+            "version-d780cbcde4ab4f52".into(),
+        )
+        .unwrap();
+
+        assert!(latest.is_newer_than(&installed));
+        assert!(installed.is_older_than(&latest));
+        assert!(!latest.is_newer_than(&latest));
+        assert_eq!(
+            installed,
+            ClientVersionInfo::new(Channel::Live, "0.553.0.5530470".into(), "".into()).unwrap()
+        );
+    }
 }
@@ -1,12 +1,83 @@
-use time::{macros::format_description, Date};
+use std::cmp::Ordering;
 
-use crate::domain::Channel;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    domain::{BinaryType, Channel},
+    Error,
+};
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct DeployLog {
     pub channel: Channel,
+    pub binary_type: BinaryType,
     pub version_guid: String,
-    pub timestamp: Date,
+    /// When the deploy happened. Deploy history is logged in US Pacific time, so this carries a -8 or -7 offset.
+    pub timestamp: OffsetDateTime,
+
+    /// Only included in modern deploy logs, older entries don't record it.
+    pub file_version: Option<(usize, usize, usize, usize)>,
+    /// Only included in modern deploy logs, older entries don't record it.
+    pub git_hash: Option<String>,
+}
+
+/// Deploy logs are ordered by when they happened, oldest first.
+impl Ord for DeployLog {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.timestamp
+            .cmp(&other.timestamp)
+            .then_with(|| self.channel.cmp(&other.channel))
+            .then_with(|| self.binary_type.cmp(&other.binary_type))
+            .then_with(|| self.version_guid.cmp(&other.version_guid))
+            .then_with(|| self.file_version.cmp(&other.file_version))
+            .then_with(|| self.git_hash.cmp(&other.git_hash))
+    }
+}
+
+impl PartialOrd for DeployLog {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A line from the deploy history that looked like a deploy log, but could not be parsed.
+#[derive(Debug)]
+pub struct SkippedLog {
+    pub line: String,
+    pub reason: Error,
+}
+
+/// Every deploy log parsed from a channel's deploy history.
+#[derive(Debug, Default)]
+pub struct DeployHistory {
+    /// Sorted oldest first.
+    pub logs: Vec<DeployLog>,
+    /// Lines which were skipped because they could not be parsed. These are kept around so callers can report them
+    /// instead of the parser silently dropping them.
+    pub skipped: Vec<SkippedLog>,
+}
+
+impl DeployHistory {
+    /// The `count` most recent deploys, newest first.
+    pub fn latest(&self, count: usize) -> impl Iterator<Item = &DeployLog> {
+        self.logs.iter().rev().take(count)
+    }
+
+    /// Every deploy that happened at or after `start`, and before `end`.
+    pub fn between(&self, start: OffsetDateTime, end: OffsetDateTime) -> &[DeployLog] {
+        let start = self.logs.partition_point(|log| log.timestamp < start);
+        let end = self.logs.partition_point(|log| log.timestamp < end);
+
+        &self.logs[start..end.max(start)]
+    }
+
+    /// The deploy of `binary_type` that was live at `instant`, which is the last one deployed at or before it.
+    pub fn live_at(&self, binary_type: &BinaryType, instant: OffsetDateTime) -> Option<&DeployLog> {
+        self.logs
+            .iter()
+            .rev()
+            .find(|log| log.binary_type == *binary_type && log.timestamp <= instant)
+    }
 }
 
 impl ToString for DeployLog {
@@ -24,16 +95,62 @@ impl ToString for DeployLog {
 
 #[cfg(test)]
 mod tests {
-    use crate::{deploy_log::DeployLog, domain::Channel};
-    use time::macros::date;
+    use crate::{
+        deploy_log::{DeployHistory, DeployLog},
+        domain::{BinaryType, Channel},
+    };
+    use time::{macros::datetime, OffsetDateTime};
 
     fn get_deploy_log() -> DeployLog {
-        let channel = Channel::Live;
+        get_deploy_log_at(
+            BinaryType::WindowsPlayer,
+            "version-d780cbcde4ab4f52",
+            datetime!(2022-01-01 0:00 -8),
+        )
+    }
 
+    fn get_deploy_log_at(
+        binary_type: BinaryType,
+        version_guid: &str,
+        timestamp: OffsetDateTime,
+    ) -> DeployLog {
         DeployLog {
-            version_guid: "version-d780cbcde4ab4f52".into(),
-            timestamp: date!(2022 - 1 - 1),
-            channel,
+            channel: Channel::Live,
+            binary_type,
+            version_guid: version_guid.into(),
+            timestamp,
+            file_version: None,
+            git_hash: None,
+        }
+    }
+
+    fn get_deploy_history() -> DeployHistory {
+        let logs = vec![
+            get_deploy_log_at(
+                BinaryType::WindowsPlayer,
+                "version-1",
+                datetime!(2022-01-01 9:00 -8),
+            ),
+            get_deploy_log_at(
+                BinaryType::MacPlayer,
+                "version-2",
+                datetime!(2022-01-01 9:30 -8),
+            ),
+            get_deploy_log_at(
+                BinaryType::WindowsPlayer,
+                "version-3",
+                datetime!(2022-01-01 15:00 -8),
+            ),
+            get_deploy_log_at(
+                BinaryType::WindowsPlayer,
+                "version-4",
+                datetime!(2022-01-02 9:00 -8),
+            ),
+        ];
+
+        DeployHistory {
+            logs,
+            skipped: vec![],
         }
     }
 
@@ -45,4 +162,63 @@ mod tests {
             "live version-d780cbcde4ab4f52 (Jan 01)"
         );
     }
+
+    #[test]
+    fn orders_same_day_deploys() {
+        let morning = get_deploy_log_at(
+            BinaryType::WindowsPlayer,
+            "version-b",
+            datetime!(2022-01-01 9:00 -8),
+        );
+        let afternoon = get_deploy_log_at(
+            BinaryType::WindowsPlayer,
+            "version-a",
+            datetime!(2022-01-01 15:00 -8),
+        );
+
+        assert!(morning < afternoon);
+    }
+
+    #[test]
+    fn latest() {
+        let history = get_deploy_history();
+        let latest: Vec<_> = history
+            .latest(2)
+            .map(|log| log.version_guid.as_str())
+            .collect();
+
+        assert_eq!(latest, ["version-4", "version-3"]);
+    }
+
+    #[test]
+    fn between() {
+        let history = get_deploy_history();
+        let between: Vec<_> = history
+            .between(datetime!(2022-01-01 9:30 -8), datetime!(2022-01-02 9:00 -8))
+            .iter()
+            .map(|log| log.version_guid.as_str())
+            .collect();
+
+        assert_eq!(between, ["version-2", "version-3"]);
+        assert!(history
+            .between(datetime!(2022-01-02 0:00 -8), datetime!(2022-01-01 0:00 -8))
+            .is_empty());
+    }
+
+    #[test]
+    fn live_at() {
+        let history = get_deploy_history();
+
+        let live = history.live_at(&BinaryType::WindowsPlayer, datetime!(2022-01-01 14:00 -8));
+        assert_eq!(live.unwrap().version_guid, "version-1");
+
+        let live = history.live_at(&BinaryType::WindowsPlayer, datetime!(2022-01-01 15:00 -8));
+        assert_eq!(live.unwrap().version_guid, "version-3");
+
+        let live = history.live_at(&BinaryType::MacPlayer, datetime!(2022-01-03 0:00 -8));
+        assert_eq!(live.unwrap().version_guid, "version-2");
+
+        let live = history.live_at(&BinaryType::WindowsPlayer, datetime!(2021-12-31 0:00 -8));
+        assert!(live.is_none());
+    }
 }
@@ -1,9 +1,19 @@
-use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{endpoints::Endpoints, Error};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub enum BinaryType {
     WindowsPlayer,
     MacPlayer,
+    WindowsStudio,
+    WindowsStudio64,
+    MacStudio,
+    /// Legacy name for the Windows player, only found in old deploy history entries.
+    Client,
+    RccService,
 }
 
 impl ToString for BinaryType {
@@ -11,24 +21,56 @@ impl ToString for BinaryType {
         let str = match self {
             BinaryType::WindowsPlayer => "WindowsPlayer",
             BinaryType::MacPlayer => "MacPlayer",
+            BinaryType::WindowsStudio => "WindowsStudio",
+            BinaryType::WindowsStudio64 => "WindowsStudio64",
+            BinaryType::MacStudio => "MacStudio",
+            BinaryType::Client => "Client",
+            BinaryType::RccService => "RccService",
         };
 
         str.to_owned()
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+impl FromStr for BinaryType {
+    type Err = Error;
+
+    /// Accepts both the names used by clientsettings and the (slightly different) names used in
+    /// `DeployHistory.txt`, where Windows Studio builds are logged as `Studio` and `Studio64`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let binary_type = match s {
+            "WindowsPlayer" => BinaryType::WindowsPlayer,
+            "MacPlayer" => BinaryType::MacPlayer,
+            "WindowsStudio" | "Studio" => BinaryType::WindowsStudio,
+            "WindowsStudio64" | "Studio64" => BinaryType::WindowsStudio64,
+            "MacStudio" => BinaryType::MacStudio,
+            "Client" => BinaryType::Client,
+            "RccService" => BinaryType::RccService,
+            _ => return Err(Error::UnknownBinaryType(s.to_owned())),
+        };
+
+        Ok(binary_type)
+    }
+}
+
+/// A release channel. Besides the well-known channels, Roblox can put users on any number of other channels
+/// (including per-user ones), which are represented by [`Channel::Custom`].
+///
+/// Channel names are case-insensitive, so they are always normalized to lowercase. Channels (de)serialize as their
+/// name, e.g. `"live"` or `"zbeta"`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Channel {
     Live,
     ZCanary,
     ZIntegration,
+    Custom(String),
 }
 
 impl Channel {
-    pub fn base_url(&self) -> String {
+    pub fn base_url(&self, endpoints: &Endpoints) -> String {
         match self {
-            Channel::Live => "https://setup.rbxcdn.com".to_owned(),
-            _ => format!("https://setup.rbxcdn.com/channel/{}", self.to_string()),
+            Channel::Live => endpoints.setup.to_owned(),
+            _ => format!("{}/channel/{}", endpoints.setup, self.to_string()),
         }
     }
 }
@@ -39,8 +81,114 @@ impl ToString for Channel {
             Channel::Live => "live",
             Channel::ZCanary => "zcanary",
             Channel::ZIntegration => "zintegration",
+            Channel::Custom(name) => name.as_str(),
         };
 
         str.to_owned()
     }
 }
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+
+        // Channel names end up in URLs, so make sure they can't be used to point somewhere else on the CDN.
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::MalformedChannel(s.to_owned()));
+        }
+
+        let channel = match name.as_str() {
+            "live" => Channel::Live,
+            "zcanary" => Channel::ZCanary,
+            "zintegration" => Channel::ZIntegration,
+            _ => Channel::Custom(name),
+        };
+
+        Ok(channel)
+    }
+}
+
+impl Serialize for Channel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Also accepts the variant names used before custom channels were supported (`"Live"`, `"ZCanary"`, etc), so
+/// existing `client.lock` files keep parsing.
+impl<'de> Deserialize<'de> for Channel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Channel::from_str(&name).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{domain::Channel, endpoints::Endpoints};
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Lock {
+        channel: Channel,
+    }
+
+    #[test]
+    fn round_trips_names() {
+        for name in ["live", "zcanary", "zintegration", "zbeta"] {
+            let channel = Channel::from_str(name).unwrap();
+            assert_eq!(channel.to_string(), name);
+        }
+
+        assert_eq!(Channel::from_str("LIVE").unwrap(), Channel::Live);
+        assert_eq!(
+            Channel::from_str("ZBeta").unwrap(),
+            Channel::Custom("zbeta".into())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        for name in ["", "  ", "../live", "z beta", "zbeta?x=1"] {
+            assert!(
+                Channel::from_str(name).is_err(),
+                "{name:?} should be malformed"
+            );
+        }
+    }
+
+    #[test]
+    fn custom_base_url() {
+        let channel = Channel::Custom("zbeta".into());
+        assert_eq!(
+            channel.base_url(&Endpoints::default()),
+            "https://setup.rbxcdn.com/channel/zbeta"
+        );
+    }
+
+    #[test]
+    fn round_trips_serde() {
+        let lock = Lock {
+            channel: Channel::Custom("zbeta".into()),
+        };
+
+        let serialized = toml::to_string(&lock).unwrap();
+        assert_eq!(serialized.trim(), r#"channel = "zbeta""#);
+        assert_eq!(toml::from_str::<Lock>(&serialized).unwrap(), lock);
+    }
+
+    #[test]
+    fn deserializes_legacy_names() {
+        let lock = toml::from_str::<Lock>(r#"channel = "ZCanary""#).unwrap();
+        assert_eq!(lock.channel, Channel::ZCanary);
+    }
+}
@@ -0,0 +1,95 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// Base URLs for every Roblox service we talk to. These default to Roblox's own hosts, but can be pointed somewhere
+/// else, such as a mirror or a local stand-in server. URLs don't include a trailing slash.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Endpoints {
+    /// Client versions and channels.
+    pub clientsettings: String,
+    /// The setup CDN, which hosts client packages and deploy history.
+    pub setup: String,
+    /// Authentication tickets.
+    pub auth: String,
+    /// Game join scripts, which are requested by the Roblox client.
+    pub assetgame: String,
+    /// The website, which the client and the authentication cookie belong to.
+    pub www: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            clientsettings: "https://clientsettings.roblox.com".to_owned(),
+            setup: "https://setup.rbxcdn.com".to_owned(),
+            auth: "https://auth.roblox.com".to_owned(),
+            assetgame: "https://assetgame.roblox.com".to_owned(),
+            www: "https://www.roblox.com".to_owned(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Strip trailing slashes from every URL, so they can be joined with paths.
+    pub fn normalized(mut self) -> Self {
+        for url in [
+            &mut self.clientsettings,
+            &mut self.setup,
+            &mut self.auth,
+            &mut self.assetgame,
+            &mut self.www,
+        ] {
+            let trimmed = url.trim_end_matches('/').len();
+            url.truncate(trimmed);
+        }
+
+        self
+    }
+
+    /// The domain the website's cookies are set for, so they're shared with its subdomains, e.g. `.roblox.com` for
+    /// `https://www.roblox.com`. `None` if the website's URL is malformed.
+    pub fn cookie_domain(&self) -> Option<String> {
+        let url = Url::parse(&self.www).ok()?;
+        let host = url.host_str()?;
+
+        let domain = match host.strip_prefix("www.") {
+            Some(domain) => format!(".{domain}"),
+            None => host.to_owned(),
+        };
+
+        Some(domain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoints::Endpoints;
+
+    #[test]
+    fn partial_overrides() {
+        let endpoints = toml::from_str::<Endpoints>(r#"setup = "http://localhost:8080/""#)
+            .unwrap()
+            .normalized();
+
+        assert_eq!(endpoints.setup, "http://localhost:8080");
+        assert_eq!(
+            endpoints.clientsettings,
+            Endpoints::default().clientsettings
+        );
+    }
+
+    #[test]
+    fn cookie_domain() {
+        assert_eq!(
+            Endpoints::default().cookie_domain().as_deref(),
+            Some(".roblox.com")
+        );
+
+        let endpoints = Endpoints {
+            www: "http://127.0.0.1:8080".to_owned(),
+            ..Endpoints::default()
+        };
+        assert_eq!(endpoints.cookie_domain().as_deref(), Some("127.0.0.1"));
+    }
+}
@@ -0,0 +1,32 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Request to Roblox failed")]
+    Network(#[from] reqwest::Error),
+
+    #[error("Failed to parse response from Roblox")]
+    MalformedResponse(#[from] serde_json::Error),
+
+    #[error("Got unexpected response from {url}: {status}")]
+    UnexpectedStatus { url: String, status: StatusCode },
+
+    #[error("Malformed version string: {0:?}")]
+    MalformedVersion(String),
+
+    #[error("Malformed timestamp: {timestamp:?}")]
+    MalformedTimestamp {
+        timestamp: String,
+        #[source]
+        source: time::error::Parse,
+    },
+
+    #[error("Unknown binary type: {0:?}")]
+    UnknownBinaryType(String),
+
+    #[error("Malformed channel name: {0:?}")]
+    MalformedChannel(String),
+}
@@ -1,88 +1,218 @@
-use anyhow::Context;
+use std::str::FromStr;
+
+use cache::{get_text, HttpCache};
 use client_version_info::ClientVersionInfo;
-use deploy_log::DeployLog;
+use deploy_log::{DeployHistory, DeployLog, SkippedLog};
 use domain::{BinaryType, Channel};
-use regex::Regex;
-use reqwest::Client;
-use time::{macros::format_description, Date};
+use endpoints::Endpoints;
+use regex::{Captures, Match, Regex};
+use reqwest::{header::COOKIE, Client};
+use retry::RetryPolicy;
+use serde::Deserialize;
+use time::{macros::format_description, PrimitiveDateTime};
+
+pub use error::{Error, Result};
 
+pub mod cache;
 pub mod client_version_info;
 pub mod deploy_log;
 pub mod domain;
+pub mod endpoints;
+mod error;
+pub mod pacific_time;
+pub mod retry;
 
-const LOG_PATTERN: &str = r"New Client (version-.+) at (\d+/\d+/\d+ \d+:\d+:\d+ [A,P]M)";
+/// Matches both legacy (`New Client version-... at ...`) and modern deploy logs. Modern logs also include the
+/// file version and git hash of the build.
+const LOG_PATTERN: &str = r"New (\w+) (version-[[:xdigit:]]+) at (\d+/\d+/\d+ \d+:\d+:\d+ [AP]M)(?:, file version: (\d+), (\d+), (\d+), (\d+))?(?:, git hash: ([[:xdigit:]]+))?";
 
-/// Pull raw deployment history from Roblox S3 bucket
-async fn get_deploy_history(client: &Client, channel: &Channel) -> anyhow::Result<String> {
-    let url = format!("{}/DeployHistory.txt", channel.base_url());
-
-    let history = client
-        .get(url)
-        .send()
-        .await
-        .context("Failed to get deploy logs from rbxcdn")?
-        .text()
-        .await
-        .context("Failed to parse deploy logs into string")?;
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserChannelResponse {
+    channel_name: String,
+}
 
-    Ok(history)
+/// Pull raw deployment history from Roblox S3 bucket
+async fn get_deploy_history(
+    client: &Client,
+    endpoints: &Endpoints,
+    cache: Option<&HttpCache>,
+    retry: &RetryPolicy,
+    channel: &Channel,
+) -> Result<String> {
+    let url = format!("{}/DeployHistory.txt", channel.base_url(endpoints));
+    get_text(client, cache, retry, &url).await
 }
 
-fn get_logs_from_string(channel: &Channel, deploy_history: String) -> Vec<DeployLog> {
+/// Parse deploy logs out of the raw deploy history. Only logs for `binary_type` are returned, or every log if no
+/// binary type is given. Logs that can't be parsed are skipped, and returned alongside the reason they were skipped.
+///
+/// Logs are sorted oldest first.
+fn get_logs_from_string(
+    channel: &Channel,
+    binary_type: Option<&BinaryType>,
+    deploy_history: String,
+) -> DeployHistory {
     let regex = Regex::new(LOG_PATTERN).unwrap();
+
+    let mut history = DeployHistory::default();
+    for capture in regex.captures_iter(&deploy_history) {
+        let log_binary_type = match BinaryType::from_str(&capture[1]) {
+            Ok(log_binary_type) => log_binary_type,
+            Err(reason) => {
+                history.skipped.push(SkippedLog {
+                    line: capture[0].to_owned(),
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        if let Some(binary_type) = binary_type {
+            if *binary_type != log_binary_type {
+                continue;
+            }
+        }
+
+        match get_log_from_capture(channel, log_binary_type, &capture) {
+            Ok(deploy_log) => history.logs.push(deploy_log),
+            Err(reason) => history.skipped.push(SkippedLog {
+                line: capture[0].to_owned(),
+                reason,
+            }),
+        }
+    }
+
+    history.logs.sort();
+    history
+}
+
+fn get_log_from_capture(
+    channel: &Channel,
+    binary_type: BinaryType,
+    capture: &Captures,
+) -> Result<DeployLog> {
     let format =
-        format_description!("[month padding:none]/[day padding:none]/[year] [hour padding:none]:[minute]:[second] [period]");
+        format_description!("[month padding:none]/[day padding:none]/[year] [hour repr:12 padding:none]:[minute]:[second] [period]");
 
-    let mut logs = vec![];
-    for capture in regex.captures_iter(&*deploy_history) {
-        let version_guid = capture[1].to_string();
-        let timestamp = Date::parse(&capture[2], &format).unwrap();
+    let version_guid = capture[2].to_string();
+    let timestamp = PrimitiveDateTime::parse(&capture[3], &format)
+        .map(pacific_time::assume_pacific)
+        .map_err(|source| Error::MalformedTimestamp {
+            timestamp: capture[3].to_owned(),
+            source,
+        })?;
 
-        let deploy_log = DeployLog {
-            channel: channel.to_owned(),
+    let file_version = match (
+        capture.get(4),
+        capture.get(5),
+        capture.get(6),
+        capture.get(7),
+    ) {
+        (Some(major_rev), Some(version), Some(patch), Some(change_list)) => {
+            let parse_part = |part: Match| {
+                part.as_str()
+                    .parse()
+                    .map_err(|_| Error::MalformedVersion(capture[0].to_owned()))
+            };
 
-            version_guid,
-            timestamp,
-        };
+            Some((
+                parse_part(major_rev)?,
+                parse_part(version)?,
+                parse_part(patch)?,
+                parse_part(change_list)?,
+            ))
+        }
+        _ => None,
+    };
 
-        logs.push(deploy_log);
-    }
+    let git_hash = capture.get(8).map(|git_hash| git_hash.as_str().to_owned());
 
-    logs
+    Ok(DeployLog {
+        channel: channel.to_owned(),
+        binary_type,
+
+        version_guid,
+        timestamp,
+        file_version,
+        git_hash,
+    })
 }
 
+/// Get every deploy log for a channel, optionally filtered down to a single binary type. The raw deploy history is
+/// cached in `cache` if one is given.
 pub async fn get_deploy_logs_for_channel(
     client: &Client,
+    endpoints: &Endpoints,
+    cache: Option<&HttpCache>,
+    retry: &RetryPolicy,
     channel: &Channel,
-) -> anyhow::Result<Vec<DeployLog>> {
-    let deploy_history = get_deploy_history(client, channel).await?;
-    let build_logs = get_logs_from_string(channel, deploy_history);
-    Ok(build_logs)
+    binary_type: Option<&BinaryType>,
+) -> Result<DeployHistory> {
+    let deploy_history = get_deploy_history(client, endpoints, cache, retry, channel).await?;
+    let history = get_logs_from_string(channel, binary_type, deploy_history);
+    Ok(history)
 }
 
 pub async fn get_latest_deploy_log_for_channel(
     client: &Client,
+    endpoints: &Endpoints,
+    cache: Option<&HttpCache>,
+    retry: &RetryPolicy,
     channel: &Channel,
     binary_type: &BinaryType,
-) -> anyhow::Result<Option<DeployLog>> {
-    let version_info = ClientVersionInfo::get(client, channel, binary_type)
-        .await
-        .context("Failed to get version info")?;
-
-    let deploy_logs = get_deploy_logs_for_channel(client, channel)
-        .await
-        .context("Failed to get latest deploy logs")?;
+) -> Result<Option<DeployLog>> {
+    let version_info =
+        ClientVersionInfo::get(client, endpoints, cache, retry, channel, binary_type).await?;
+    let history =
+        get_deploy_logs_for_channel(client, endpoints, cache, retry, channel, Some(binary_type))
+            .await?;
 
-    let latest_log = deploy_logs
+    let latest_log = history
+        .logs
         .into_iter()
         .find(|i| i.channel == *channel && i.version_guid == version_info.version_guid);
 
     Ok(latest_log)
 }
 
+/// Resolve the channel Roblox has assigned to the authenticated user. Most users are on [`Channel::Live`], but
+/// testers can be put on other channels without needing a different build of the launcher.
+///
+/// `cookie` is sent as the `Cookie` header, so it must include the user's `.ROBLOSECURITY` cookie.
+pub async fn get_user_channel(
+    client: &Client,
+    endpoints: &Endpoints,
+    retry: &RetryPolicy,
+    binary_type: &BinaryType,
+    cookie: &str,
+) -> Result<Channel> {
+    let url = format!(
+        "{}/v2/user-channel?binaryType={}",
+        endpoints.clientsettings,
+        binary_type.to_string()
+    );
+
+    let response = retry
+        .send(|| client.get(&url).header(COOKIE, cookie))
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::UnexpectedStatus { url, status });
+    }
+
+    let response = response.json::<UserChannelResponse>().await?;
+    Channel::from_str(&response.channel_name)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{domain::Channel, get_logs_from_string};
+    use crate::{
+        domain::{BinaryType, Channel},
+        get_logs_from_string, Error,
+    };
+    use time::macros::datetime;
 
     #[test]
     fn captures_multi_line() {
@@ -102,21 +232,104 @@ mod tests {
         New RccService version-ae2ebf93ac594514 at 1/8/2010 11:31:45 AM... Done!
         New RccService version-18f76f9455204d6b at 1/8/2010 12:18:28 PM... Done!
         New Client version-29d1896c5e90402b at 1/8/2010 1:16:46 PM... Done!";
-        let logs = get_logs_from_string(&Channel::Live, test.to_string());
+        let logs = get_logs_from_string(&Channel::Live, None, test.to_string()).logs;
+        assert_eq!(logs.len(), 17);
+
+        let logs =
+            get_logs_from_string(&Channel::Live, Some(&BinaryType::Client), test.to_string()).logs;
         assert_eq!(logs.len(), 6);
     }
 
     #[test]
     fn captures_single_line() {
         let test = "New Client version-133721681a5245bb at 11/10/2009 11:39:38 PM... Done!";
-        let logs = get_logs_from_string(&Channel::Live, test.to_string());
+        let logs = get_logs_from_string(&Channel::Live, None, test.to_string()).logs;
         assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].binary_type, BinaryType::Client);
+        assert_eq!(logs[0].timestamp, datetime!(2009-11-10 23:39:38 -8));
+        assert_eq!(logs[0].file_version, None);
+        assert_eq!(logs[0].git_hash, None);
     }
 
     #[test]
-    fn skips_bad_types() {
+    fn captures_modern_line() {
         let test = "New WindowsPlayer version-4981d7ba0b92417b at 6/14/2021 3:26:16 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...";
-        let logs = get_logs_from_string(&Channel::Live, test.to_string());
-        assert!(logs.is_empty());
+        let logs = get_logs_from_string(&Channel::Live, None, test.to_string()).logs;
+        assert_eq!(logs.len(), 1);
+
+        let log = &logs[0];
+        assert_eq!(log.binary_type, BinaryType::WindowsPlayer);
+        assert_eq!(log.version_guid, "version-4981d7ba0b92417b");
+        assert_eq!(log.timestamp, datetime!(2021-06-14 15:26:16 -7));
+        assert_eq!(log.file_version, Some((0, 483, 0, 424775)));
+        assert_eq!(
+            log.git_hash.as_deref(),
+            Some("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+        );
+    }
+
+    #[test]
+    fn captures_studio_types() {
+        let test = "New Studio64 version-25b5a3a5a3a54b1c at 6/14/2021 3:20:01 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...
+        New MacStudio version-5c0d5e1b2c8e4d3f at 6/14/2021 3:22:45 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...
+        New MacPlayer version-0a3f1e8c9f5d4b2a at 6/14/2021 3:24:10 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...";
+        let logs = get_logs_from_string(&Channel::Live, None, test.to_string()).logs;
+
+        let binary_types: Vec<_> = logs.iter().map(|log| log.binary_type).collect();
+        assert_eq!(
+            binary_types,
+            [
+                BinaryType::WindowsStudio64,
+                BinaryType::MacStudio,
+                BinaryType::MacPlayer
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_by_binary_type() {
+        let test = "New WindowsPlayer version-4981d7ba0b92417b at 6/14/2021 3:26:16 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...
+        New MacPlayer version-0a3f1e8c9f5d4b2a at 6/14/2021 3:24:10 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...";
+        let logs = get_logs_from_string(
+            &Channel::Live,
+            Some(&BinaryType::MacPlayer),
+            test.to_string(),
+        )
+        .logs;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].version_guid, "version-0a3f1e8c9f5d4b2a");
+    }
+
+    #[test]
+    fn skips_bad_types() {
+        let test = "New Bootstrapper version-4981d7ba0b92417b at 6/14/2021 3:26:16 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...";
+        let history = get_logs_from_string(&Channel::Live, None, test.to_string());
+        assert!(history.logs.is_empty());
+        assert_eq!(history.skipped.len(), 1);
+        assert!(matches!(
+            history.skipped[0].reason,
+            Error::UnknownBinaryType(_)
+        ));
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let test = "New WindowsPlayer version-4981d7ba0b92417b at 13/45/2021 3:26:16 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...
+        New WindowsPlayer version-5a2b3c4d5e6f7a8b at 6/14/2021 3:26:16 PM, file version: 0, 483, 0, 99999999999999999999999, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...
+        New WindowsPlayer version-0a3f1e8c9f5d4b2a at 6/15/2021 3:24:10 PM, file version: 0, 483, 0, 424775, git hash: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa ...";
+        let history = get_logs_from_string(&Channel::Live, None, test.to_string());
+
+        assert_eq!(history.logs.len(), 1);
+        assert_eq!(history.logs[0].version_guid, "version-0a3f1e8c9f5d4b2a");
+
+        assert_eq!(history.skipped.len(), 2);
+        assert!(matches!(
+            history.skipped[0].reason,
+            Error::MalformedTimestamp { .. }
+        ));
+        assert!(matches!(
+            history.skipped[1].reason,
+            Error::MalformedVersion(_)
+        ));
     }
 }
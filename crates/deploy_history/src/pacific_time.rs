@@ -0,0 +1,121 @@
+//! Deploy history timestamps are logged in US Pacific time, which `time` has no built-in support for. This is just
+//! enough of the US daylight saving rules to turn those timestamps into real instants.
+
+use time::{
+    macros::{offset, time},
+    Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, UtcOffset,
+};
+
+const PST: UtcOffset = offset!(-8);
+const PDT: UtcOffset = offset!(-7);
+
+/// Treat a local Pacific time as an instant. Times that happen twice when clocks go back are assumed to be the first
+/// (daylight saving) one.
+pub fn assume_pacific(datetime: PrimitiveDateTime) -> OffsetDateTime {
+    let offset = if is_daylight_saving(datetime) {
+        PDT
+    } else {
+        PST
+    };
+
+    datetime.assume_offset(offset)
+}
+
+fn is_daylight_saving(datetime: PrimitiveDateTime) -> bool {
+    let year = datetime.year();
+
+    // The rules changed in 2007. Older deploy logs go back to 2009, but be correct for earlier dates anyway.
+    let (start, end) = if year >= 2007 {
+        (
+            nth_sunday(year, Month::March, 2),
+            nth_sunday(year, Month::November, 1),
+        )
+    } else {
+        (
+            nth_sunday(year, Month::April, 1),
+            last_sunday(year, Month::October),
+        )
+    };
+
+    // Clocks change at 2AM local time.
+    let start = PrimitiveDateTime::new(start, time!(2:00));
+    let end = PrimitiveDateTime::new(end, time!(2:00));
+
+    datetime >= start && datetime < end
+}
+
+fn nth_sunday(year: i32, month: Month, n: u8) -> Date {
+    let first = Date::from_calendar_date(year, month, 1).expect("valid date");
+    let days_until_sunday = (7 - first.weekday().number_days_from_sunday()) % 7;
+
+    first + Duration::days((days_until_sunday + 7 * (n - 1)) as i64)
+}
+
+fn last_sunday(year: i32, month: Month) -> Date {
+    let next_year = if month == Month::December {
+        year + 1
+    } else {
+        year
+    };
+    let last =
+        Date::from_calendar_date(next_year, month.next(), 1).expect("valid date") - Duration::DAY;
+
+    last - Duration::days(last.weekday().number_days_from_sunday() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::pacific_time::assume_pacific;
+
+    #[test]
+    fn standard_time() {
+        let instant = assume_pacific(datetime!(2022-01-15 12:00));
+        assert_eq!(instant, datetime!(2022-01-15 20:00 UTC));
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        let instant = assume_pacific(datetime!(2022-07-15 12:00));
+        assert_eq!(instant, datetime!(2022-07-15 19:00 UTC));
+    }
+
+    #[test]
+    fn transitions() {
+        // 2022: DST started on March 13th and ended on November 6th.
+        assert_eq!(
+            assume_pacific(datetime!(2022-03-13 1:59)),
+            datetime!(2022-03-13 9:59 UTC)
+        );
+        assert_eq!(
+            assume_pacific(datetime!(2022-03-13 3:00)),
+            datetime!(2022-03-13 10:00 UTC)
+        );
+        assert_eq!(
+            assume_pacific(datetime!(2022-11-06 1:30)),
+            datetime!(2022-11-06 8:30 UTC)
+        );
+        assert_eq!(
+            assume_pacific(datetime!(2022-11-06 2:00)),
+            datetime!(2022-11-06 10:00 UTC)
+        );
+    }
+
+    #[test]
+    fn pre_2007_rules() {
+        // 2006: DST started on April 2nd and ended on October 29th.
+        assert_eq!(
+            assume_pacific(datetime!(2006-03-20 12:00)),
+            datetime!(2006-03-20 20:00 UTC)
+        );
+        assert_eq!(
+            assume_pacific(datetime!(2006-10-28 12:00)),
+            datetime!(2006-10-28 19:00 UTC)
+        );
+        assert_eq!(
+            assume_pacific(datetime!(2006-10-30 12:00)),
+            datetime!(2006-10-30 20:00 UTC)
+        );
+    }
+}
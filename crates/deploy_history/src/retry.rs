@@ -0,0 +1,414 @@
+//! Retrying requests to Roblox which fail for reasons that are likely to go away, such as rate limiting, server
+//! errors and dropped connections.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+/// Whether a failed attempt is worth trying again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// Trying again won't help, e.g. a 404.
+    Never,
+    /// Try again after backing off.
+    Backoff,
+    /// Try again after this long, as asked for by a `Retry-After` header.
+    After(Duration),
+}
+
+/// Source of time for retries, so tests don't have to actually wait.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock which returns from sleeps straight away, moving its time forward instead. Every sleep is recorded.
+#[derive(Debug)]
+pub struct FakeClock {
+    now: Mutex<SystemTime>,
+    sleeps: Mutex<Vec<Duration>>,
+}
+
+impl FakeClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+            sleeps: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        *self.now.lock().unwrap() += duration;
+        self.sleeps.lock().unwrap().push(duration);
+        Box::pin(async {})
+    }
+}
+
+/// How failed requests are retried. Backoff doubles after every retry, with jitter so that many launchers failing
+/// at once don't all retry at once.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// How many times a request is made in total, including the first. `1` turns retries off.
+    pub max_attempts: u32,
+    /// Backoff before the first retry, in milliseconds.
+    pub initial_backoff_ms: u64,
+    /// Longest wait between attempts, in milliseconds. If Roblox asks us to wait longer than this with
+    /// `Retry-After`, we give up instead.
+    pub max_backoff_ms: u64,
+    #[serde(skip, default = "system_clock")]
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            clock: system_clock(),
+        }
+    }
+}
+
+fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// How many times a request is made in total. Clamped to at least 1.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Run `operation` until it succeeds, fails with [`Retry::Never`], or runs out of attempts. The error from the
+    /// last attempt is returned.
+    pub async fn run<T, E, F, Fut>(&self, mut operation: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, (E, Retry)>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            let (error, retry) = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(failure) => failure,
+            };
+
+            if attempt >= self.max_attempts {
+                return Err(error);
+            }
+
+            let delay = match retry {
+                Retry::Never => return Err(error),
+                Retry::Backoff => self.backoff(attempt),
+                Retry::After(delay) if delay <= self.max_backoff() => delay,
+                Retry::After(_) => return Err(error),
+            };
+
+            self.clock.sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send the request built by `request`, retrying dropped connections, timeouts, rate limiting and server errors.
+    /// Once out of attempts, the last response is returned as is, even if it's an error status.
+    pub async fn send<F>(&self, request: F) -> reqwest::Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let result = self
+            .run(|| {
+                let request = request();
+                async move {
+                    match request.send().await {
+                        Ok(response) => match self.retry_for_response(&response) {
+                            Retry::Never => Ok(response),
+                            retry => Err((Ok(response), retry)),
+                        },
+                        Err(error) => {
+                            let retry = retry_for_error(&error);
+                            Err((Err(error), retry))
+                        }
+                    }
+                }
+            })
+            .await;
+
+        match result {
+            Ok(response) => Ok(response),
+            Err(last) => last,
+        }
+    }
+
+    /// Whether a response's status is worth retrying, honouring its `Retry-After` header.
+    pub fn retry_for_response(&self, response: &Response) -> Retry {
+        retry_for_status(response.status(), response.headers(), self.clock.now())
+    }
+
+    /// Backoff before retry number `retry`, starting from 1. Always at least half of the exponential backoff, so
+    /// retries still spread out when the jitter is unlucky.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff_ms
+            .saturating_mul(1 << (retry - 1).min(16))
+            .min(self.max_backoff_ms);
+
+        let jittered = exponential / 2 + rand::thread_rng().gen_range(0..=exponential / 2);
+        Duration::from_millis(jittered)
+    }
+
+    fn max_backoff(&self) -> Duration {
+        Duration::from_millis(self.max_backoff_ms)
+    }
+}
+
+/// Whether a request which failed to get a response is worth retrying.
+pub fn retry_for_error(error: &reqwest::Error) -> Retry {
+    if error.is_timeout() || error.is_connect() || error.is_request() || error.is_body() {
+        Retry::Backoff
+    } else {
+        Retry::Never
+    }
+}
+
+/// Whether a response with `status` is worth retrying. `Retry-After` may be given as seconds or as a date, which is
+/// compared against `now`.
+pub fn retry_for_status(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Retry {
+    let retryable = matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    );
+
+    if !retryable {
+        return Retry::Never;
+    }
+
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, now));
+
+    match retry_after {
+        Some(delay) => Retry::After(delay),
+        None => Retry::Backoff,
+    }
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    // A date in the past means we can retry straight away.
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+
+    use crate::retry::{retry_for_status, FakeClock, Retry, RetryPolicy};
+
+    fn get_policy() -> (RetryPolicy, Arc<FakeClock>) {
+        let clock = Arc::new(FakeClock::new(SystemTime::UNIX_EPOCH));
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            ..Default::default()
+        }
+        .with_clock(clock.clone());
+
+        (policy, clock)
+    }
+
+    /// Fails with `retry` the first `failures` times it's called.
+    async fn run_failing(
+        policy: &RetryPolicy,
+        failures: u32,
+        retry: Retry,
+    ) -> (Result<(), ()>, u32) {
+        let attempts = AtomicU32::new(0);
+        let result = policy
+            .run(|| async {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+                if attempt <= failures {
+                    Err(((), retry))
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        (result, attempts.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn backs_off_exponentially() {
+        let (policy, clock) = get_policy();
+
+        let (result, attempts) = run_failing(&policy, 4, Retry::Backoff).await;
+        assert!(result.is_ok());
+        assert_eq!(attempts, 5);
+
+        let sleeps = clock.sleeps();
+        assert_eq!(sleeps.len(), 4);
+        for (sleep, cap) in sleeps.iter().zip([100, 200, 400, 800]) {
+            let cap = Duration::from_millis(cap);
+            assert!(
+                *sleep >= cap / 2 && *sleep <= cap,
+                "{sleep:?} outside {cap:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn caps_backoff() {
+        let (mut policy, clock) = get_policy();
+        policy.max_attempts = 10;
+
+        run_failing(&policy, 9, Retry::Backoff).await.0.unwrap();
+
+        assert!(clock
+            .sleeps()
+            .iter()
+            .all(|sleep| *sleep <= Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (policy, clock) = get_policy();
+
+        let (result, attempts) = run_failing(&policy, 10, Retry::Backoff).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 5);
+        assert_eq!(clock.sleeps().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn never_retries_permanent_failures() {
+        let (policy, clock) = get_policy();
+
+        let (result, attempts) = run_failing(&policy, 10, Retry::Never).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let (policy, clock) = get_policy();
+
+        run_failing(&policy, 1, Retry::After(Duration::from_millis(750)))
+            .await
+            .0
+            .unwrap();
+        assert_eq!(clock.sleeps(), [Duration::from_millis(750)]);
+
+        // Longer than we're willing to wait.
+        let (result, attempts) =
+            run_failing(&policy, 1, Retry::After(Duration::from_secs(60))).await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn classifies_statuses() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_470);
+        let empty = HeaderMap::new();
+
+        assert_eq!(
+            retry_for_status(StatusCode::NOT_FOUND, &empty, now),
+            Retry::Never
+        );
+        assert_eq!(
+            retry_for_status(StatusCode::BAD_GATEWAY, &empty, now),
+            Retry::Backoff
+        );
+
+        let mut seconds = HeaderMap::new();
+        seconds.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(
+            retry_for_status(StatusCode::TOO_MANY_REQUESTS, &seconds, now),
+            Retry::After(Duration::from_secs(3))
+        );
+
+        // 10 seconds after `now`.
+        let mut date = HeaderMap::new();
+        date.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(
+            retry_for_status(StatusCode::SERVICE_UNAVAILABLE, &date, now),
+            Retry::After(Duration::from_secs(10))
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::{Args, Parser, Subcommand};
+use deploy_history::{
+    cache::HttpCache,
+    client_version_info::ClientVersionInfo,
+    domain::{BinaryType, Channel},
+    endpoints::Endpoints,
+    get_deploy_logs_for_channel, pacific_time,
+    retry::RetryPolicy,
+};
+use reqwest::Client;
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime,
+    PrimitiveDateTime,
+};
+
+use crate::output::{print_rows, DeployRow, Format, VersionRow};
+
+mod output;
+
+/// Query Roblox's deploy history.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// How to print results.
+    #[arg(long, value_enum, default_value_t = Format::Table, global = true)]
+    format: Format,
+
+    /// Cache responses from Roblox in this directory, and fall back to them when Roblox can't be reached.
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// Use a different clientsettings host, such as a mirror.
+    #[arg(long, global = true)]
+    clientsettings_url: Option<String>,
+
+    /// Use a different setup CDN host, such as a mirror.
+    #[arg(long, global = true)]
+    setup_url: Option<String>,
+
+    /// How many times to try each request to Roblox before giving up.
+    #[arg(long, global = true, default_value_t = RetryPolicy::default().max_attempts)]
+    max_attempts: u32,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the most recent deploys, newest first.
+    List {
+        #[command(flatten)]
+        target: Target,
+
+        /// How many deploys to list.
+        #[arg(long, default_value_t = 20)]
+        count: usize,
+
+        /// Only list deploys at or after this time.
+        #[arg(long, value_parser = parse_instant)]
+        since: Option<OffsetDateTime>,
+
+        /// Only list deploys before this time.
+        #[arg(long, value_parser = parse_instant)]
+        until: Option<OffsetDateTime>,
+    },
+
+    /// Show the version that is currently live.
+    Current {
+        #[command(flatten)]
+        target: Target,
+    },
+
+    /// Find the version that was live at a given time.
+    LiveAt {
+        /// RFC 3339 timestamp, or `YYYY-MM-DD [HH:MM[:SS]]` in US Pacific time.
+        #[arg(value_parser = parse_instant)]
+        instant: OffsetDateTime,
+
+        #[command(flatten)]
+        target: Target,
+    },
+}
+
+#[derive(Debug, Args)]
+struct Target {
+    /// Release channel, e.g. `live` or `zcanary`.
+    #[arg(long, default_value = "live")]
+    channel: Channel,
+
+    /// Binary type, e.g. `WindowsPlayer` or `MacPlayer`.
+    #[arg(long, default_value = "WindowsPlayer")]
+    binary_type: BinaryType,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let client = Client::new();
+    let cache = cli.cache_dir.map(HttpCache::new);
+    let retry = RetryPolicy::default().with_max_attempts(cli.max_attempts);
+
+    let mut endpoints = Endpoints::default();
+    if let Some(clientsettings_url) = cli.clientsettings_url {
+        endpoints.clientsettings = clientsettings_url;
+    }
+    if let Some(setup_url) = cli.setup_url {
+        endpoints.setup = setup_url;
+    }
+    let endpoints = endpoints.normalized();
+
+    match cli.command {
+        Command::List {
+            target,
+            count,
+            since,
+            until,
+        } => {
+            let history = get_deploy_logs_for_channel(
+                &client,
+                &endpoints,
+                cache.as_ref(),
+                &retry,
+                &target.channel,
+                Some(&target.binary_type),
+            )
+            .await
+            .context("Failed to get deploy history")?;
+
+            report_skipped(history.skipped.len());
+
+            let since = since.unwrap_or(OffsetDateTime::UNIX_EPOCH);
+            let until = until.unwrap_or_else(OffsetDateTime::now_utc);
+
+            let rows = history
+                .between(since, until)
+                .iter()
+                .rev()
+                .take(count)
+                .map(DeployRow::from)
+                .collect::<Vec<_>>();
+
+            print_rows(&rows, cli.format)?;
+        }
+        Command::Current { target } => {
+            let version_info = ClientVersionInfo::get(
+                &client,
+                &endpoints,
+                cache.as_ref(),
+                &retry,
+                &target.channel,
+                &target.binary_type,
+            )
+            .await
+            .context("Failed to get current version")?;
+
+            let row = VersionRow::new(&version_info, &target.binary_type);
+            print_rows(&[row], cli.format)?;
+        }
+        Command::LiveAt { instant, target } => {
+            let history = get_deploy_logs_for_channel(
+                &client,
+                &endpoints,
+                cache.as_ref(),
+                &retry,
+                &target.channel,
+                Some(&target.binary_type),
+            )
+            .await
+            .context("Failed to get deploy history")?;
+
+            report_skipped(history.skipped.len());
+
+            let Some(deploy_log) = history.live_at(&target.binary_type, instant) else {
+                bail!(
+                    "No {} deploys on {} at or before {instant}",
+                    target.binary_type.to_string(),
+                    target.channel.to_string()
+                );
+            };
+
+            print_rows(&[DeployRow::from(deploy_log)], cli.format)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn report_skipped(skipped: usize) {
+    if skipped > 0 {
+        eprintln!("Skipped {skipped} malformed lines in the deploy history");
+    }
+}
+
+/// Parses an RFC 3339 timestamp, or a local US Pacific date and time (the same as deploy history timestamps). A date
+/// on its own is the start of that day.
+fn parse_instant(s: &str) -> anyhow::Result<OffsetDateTime> {
+    if let Ok(instant) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(instant);
+    }
+
+    let date_time_formats = [
+        format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+        format_description!("[year]-[month]-[day] [hour]:[minute]"),
+    ];
+
+    for format in date_time_formats {
+        if let Ok(date_time) = PrimitiveDateTime::parse(s, format) {
+            return Ok(pacific_time::assume_pacific(date_time));
+        }
+    }
+
+    if let Ok(date) = Date::parse(s, format_description!("[year]-[month]-[day]")) {
+        return Ok(pacific_time::assume_pacific(date.midnight()));
+    }
+
+    bail!("Expected an RFC 3339 timestamp or `YYYY-MM-DD [HH:MM[:SS]]`, got {s:?}");
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::parse_instant;
+
+    #[test]
+    fn parses_instants() {
+        assert_eq!(
+            parse_instant("2022-11-14T23:30:00Z").unwrap(),
+            datetime!(2022-11-14 23:30 UTC)
+        );
+        assert_eq!(
+            parse_instant("2022-11-14 15:30").unwrap(),
+            datetime!(2022-11-14 15:30 -8)
+        );
+        assert_eq!(
+            parse_instant("2022-07-14 15:30:10").unwrap(),
+            datetime!(2022-07-14 15:30:10 -7)
+        );
+        assert_eq!(
+            parse_instant("2022-11-14").unwrap(),
+            datetime!(2022-11-14 0:00 -8)
+        );
+        assert!(parse_instant("yesterday").is_err());
+    }
+}
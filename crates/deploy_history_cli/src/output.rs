@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use deploy_history::{
+    client_version_info::ClientVersionInfo, deploy_log::DeployLog, domain::BinaryType,
+};
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// A row of output. Rows are printed as objects for JSON, and as columns for tables and CSV.
+pub trait Row: Serialize {
+    fn headers() -> &'static [&'static str];
+    fn columns(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeployRow {
+    channel: String,
+    binary_type: String,
+    version_guid: String,
+    timestamp: String,
+    file_version: Option<String>,
+    git_hash: Option<String>,
+}
+
+impl From<&DeployLog> for DeployRow {
+    fn from(log: &DeployLog) -> Self {
+        let file_version = log
+            .file_version
+            .map(|(major_rev, version, patch, change_list)| {
+                format!("{major_rev}.{version}.{patch}.{change_list}")
+            });
+
+        Self {
+            channel: log.channel.to_string(),
+            binary_type: log.binary_type.to_string(),
+            version_guid: log.version_guid.to_owned(),
+            timestamp: log.timestamp.format(&Rfc3339).expect("valid timestamp"),
+            file_version,
+            git_hash: log.git_hash.to_owned(),
+        }
+    }
+}
+
+impl Row for DeployRow {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "channel",
+            "binary_type",
+            "version_guid",
+            "timestamp",
+            "file_version",
+            "git_hash",
+        ]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.channel.to_owned(),
+            self.binary_type.to_owned(),
+            self.version_guid.to_owned(),
+            self.timestamp.to_owned(),
+            self.file_version.to_owned().unwrap_or_default(),
+            self.git_hash.to_owned().unwrap_or_default(),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionRow {
+    channel: String,
+    binary_type: String,
+    version: String,
+    version_guid: String,
+}
+
+impl VersionRow {
+    pub fn new(version_info: &ClientVersionInfo, binary_type: &BinaryType) -> Self {
+        Self {
+            channel: version_info.channel.to_string(),
+            binary_type: binary_type.to_string(),
+            version: version_info.to_string(),
+            version_guid: version_info.version_guid.to_owned(),
+        }
+    }
+}
+
+impl Row for VersionRow {
+    fn headers() -> &'static [&'static str] {
+        &["channel", "binary_type", "version", "version_guid"]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.channel.to_owned(),
+            self.binary_type.to_owned(),
+            self.version.to_owned(),
+            self.version_guid.to_owned(),
+        ]
+    }
+}
+
+pub fn print_rows<R: Row>(rows: &[R], format: Format) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write_rows(&mut stdout, rows, format)
+}
+
+fn write_rows<R: Row>(writer: &mut impl Write, rows: &[R], format: Format) -> anyhow::Result<()> {
+    match format {
+        Format::Table => write_table(writer, rows).context("Failed to write table")?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *writer, rows).context("Failed to write JSON")?;
+            writeln!(writer)?;
+        }
+        Format::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            csv.write_record(R::headers())?;
+            for row in rows {
+                csv.write_record(row.columns())?;
+            }
+            csv.flush().context("Failed to write CSV")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_table<R: Row>(writer: &mut impl Write, rows: &[R]) -> io::Result<()> {
+    let rows: Vec<_> = rows.iter().map(Row::columns).collect();
+
+    let mut widths: Vec<_> = R::headers().iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    let mut write_line = |columns: &[&str]| {
+        let line = columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        writeln!(writer, "{}", line.trim_end())
+    };
+
+    write_line(R::headers())?;
+    for row in &rows {
+        write_line(&row.iter().map(String::as_str).collect::<Vec<_>>())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use deploy_history::{
+        deploy_log::DeployLog,
+        domain::{BinaryType, Channel},
+    };
+    use time::macros::datetime;
+
+    use crate::output::{write_rows, DeployRow, Format};
+
+    fn get_rows() -> Vec<DeployRow> {
+        let logs = [
+            DeployLog {
+                channel: Channel::Live,
+                binary_type: BinaryType::WindowsPlayer,
+                version_guid: "version-4981d7ba0b92417b".into(),
+                timestamp: datetime!(2021-06-14 15:26:16 -7),
+                file_version: Some((0, 483, 0, 424775)),
+                git_hash: Some("aaaaaaaa".into()),
+            },
+            DeployLog {
+                channel: Channel::Live,
+                binary_type: BinaryType::WindowsPlayer,
+                version_guid: "version-133721681a5245bb".into(),
+                timestamp: datetime!(2009-11-10 23:39:38 -8),
+                file_version: None,
+                git_hash: None,
+            },
+        ];
+
+        logs.iter().map(DeployRow::from).collect()
+    }
+
+    fn write(format: Format) -> String {
+        let mut output = Vec::new();
+        write_rows(&mut output, &get_rows(), format).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_table() {
+        assert_eq!(
+            write(Format::Table),
+            "\
+channel  binary_type    version_guid              timestamp                  file_version    git_hash
+live     WindowsPlayer  version-4981d7ba0b92417b  2021-06-14T15:26:16-07:00  0.483.0.424775  aaaaaaaa
+live     WindowsPlayer  version-133721681a5245bb  2009-11-10T23:39:38-08:00
+"
+        );
+    }
+
+    #[test]
+    fn writes_csv() {
+        assert_eq!(
+            write(Format::Csv),
+            "\
+channel,binary_type,version_guid,timestamp,file_version,git_hash
+live,WindowsPlayer,version-4981d7ba0b92417b,2021-06-14T15:26:16-07:00,0.483.0.424775,aaaaaaaa
+live,WindowsPlayer,version-133721681a5245bb,2009-11-10T23:39:38-08:00,,
+"
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let output: serde_json::Value = serde_json::from_str(&write(Format::Json)).unwrap();
+        assert_eq!(output[0]["file_version"], "0.483.0.424775");
+        assert_eq!(output[1]["file_version"], serde_json::Value::Null);
+    }
+}
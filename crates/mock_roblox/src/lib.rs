@@ -0,0 +1,546 @@
+//! A local stand-in for the Roblox services the bootstrapper talks to: clientsettings, the setup CDN (including
+//! `DeployHistory.txt` and client packages) and the authentication ticket endpoints. Every service is served from
+//! one host, so [`MockRoblox::endpoints`] can be passed anywhere an [`Endpoints`] is expected.
+//!
+//! Nothing is served until it's registered, so each test sets up exactly the versions and files it needs.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future,
+    io::{Cursor, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use deploy_history::endpoints::Endpoints;
+use hyper::{
+    body,
+    header::{
+        HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, COOKIE, RANGE,
+    },
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Method, Request, Response, Server, StatusCode,
+};
+use tokio::sync::oneshot;
+use zip::{write::FileOptions, ZipWriter};
+
+/// The `.ROBLOSECURITY` cookie the mock accepts.
+pub const ROBLOSECURITY: &str = "_|WARNING:-DO-NOT-SHARE-THIS.--mock-roblosecurity";
+
+/// The X-CSRF token handed out by `/v2/logout`.
+pub const CSRF_TOKEN: &str = "mock-csrf-token";
+
+/// The ticket handed out by `/v1/authentication-ticket`.
+pub const AUTHENTICATION_TICKET: &str = "mock-authentication-ticket";
+
+/// A request the mock received, so tests can check what was asked for.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    /// Path and query, e.g. `/v2/user-channel?binaryType=MacPlayer`.
+    pub path: String,
+    pub headers: HeaderMap,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// `(binary type, channel)` to `(version, version GUID)`.
+    versions: HashMap<(String, String), (String, String)>,
+    user_channel: Option<String>,
+    /// Setup CDN files by path, e.g. `/mac/version-abc-RobloxPlayer.zip`.
+    files: HashMap<String, Vec<u8>>,
+    /// Paths which fail before being served normally, with the status to fail with and how many more times to fail.
+    failures: HashMap<String, (StatusCode, usize)>,
+    /// Like `failures`, but only for requests with a `Range` header.
+    range_failures: HashMap<String, (StatusCode, usize)>,
+    /// Paths which are never answered before being served normally, with how many more times to stall.
+    stalls: HashMap<String, usize>,
+    /// Like `stalls`, but only for requests with a `Range` header, and half of the range is sent first.
+    range_stalls: HashMap<String, usize>,
+    /// Bodies of stalled responses, kept open so they never finish.
+    stalled_bodies: Vec<body::Sender>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// A running mock server. The server shuts down when this is dropped.
+#[derive(Debug)]
+pub struct MockRoblox {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockRoblox {
+    /// Start serving on a free local port. Must be called from within a Tokio runtime.
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let service_state = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let state = Arc::clone(&service_state);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = handle(&state, request);
+                    async move {
+                        match response {
+                            Some(response) => Ok::<_, Infallible>(response),
+                            None => future::pending().await,
+                        }
+                    }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .expect("failed to bind mock Roblox server")
+            .serve(make_service);
+        let addr = server.local_addr();
+
+        let (shutdown, shutdown_receiver) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = shutdown_receiver.await;
+        }));
+
+        Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// Base URL of the server, without a trailing slash.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Endpoints which point every service at this server.
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints {
+            clientsettings: self.url(),
+            setup: self.url(),
+            auth: self.url(),
+            assetgame: self.url(),
+            www: self.url(),
+        }
+    }
+
+    /// Set the latest version of `binary_type` on `channel`, as reported by clientsettings.
+    pub fn set_client_version(
+        &self,
+        binary_type: &str,
+        channel: &str,
+        version: &str,
+        version_guid: &str,
+    ) {
+        self.state().versions.insert(
+            (binary_type.to_owned(), channel.to_owned()),
+            (version.to_owned(), version_guid.to_owned()),
+        );
+    }
+
+    /// Set the channel reported for the authenticated user.
+    pub fn set_user_channel(&self, channel: &str) {
+        self.state().user_channel = Some(channel.to_owned());
+    }
+
+    /// Serve `contents` from the setup CDN at `path`, e.g. `/DeployHistory.txt`.
+    pub fn add_file(&self, path: &str, contents: impl Into<Vec<u8>>) {
+        self.state().files.insert(path.to_owned(), contents.into());
+    }
+
+    /// Fail the next `times` requests for `path` with `status`, e.g. to test retries. Requests are served normally
+    /// after that.
+    pub fn fail_requests(&self, path: &str, status: StatusCode, times: usize) {
+        self.state()
+            .failures
+            .insert(path.to_owned(), (status, times));
+    }
+
+    /// Like [`fail_requests`](Self::fail_requests), but only for requests for part of the file, i.e. with a `Range`
+    /// header. A `HEAD` for its size is served normally.
+    pub fn fail_range_requests(&self, path: &str, status: StatusCode, times: usize) {
+        self.state()
+            .range_failures
+            .insert(path.to_owned(), (status, times));
+    }
+
+    /// Never answer the next `times` requests for `path`, e.g. to test timeouts. Requests are served normally after
+    /// that.
+    pub fn stall_requests(&self, path: &str, times: usize) {
+        self.state().stalls.insert(path.to_owned(), times);
+    }
+
+    /// Like [`stall_requests`](Self::stall_requests), but only for requests for part of the file, i.e. with a `Range`
+    /// header. Half of the range is sent before the response stalls, like a connection which drops silently.
+    pub fn stall_range_requests(&self, path: &str, times: usize) {
+        self.state().range_stalls.insert(path.to_owned(), times);
+    }
+
+    /// Every request received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for MockRoblox {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Build a ZIP archive in memory, e.g. to serve as a client package. Paths ending in `/` are added as directories.
+/// Files are marked as executable, like the Roblox player is.
+pub fn zip_package(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().unix_permissions(0o755);
+
+    for (path, contents) in files {
+        if path.ends_with('/') {
+            writer.add_directory(*path, options).unwrap();
+        } else {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+    }
+
+    writer.finish().unwrap().into_inner()
+}
+
+/// The response to `request`, or `None` if it's stalled.
+fn handle(state: &Mutex<State>, request: Request<Body>) -> Option<Response<Body>> {
+    let mut state = state.lock().unwrap();
+
+    state.requests.push(RecordedRequest {
+        method: request.method().to_owned(),
+        path: request
+            .uri()
+            .path_and_query()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        headers: request.headers().to_owned(),
+    });
+
+    let path = request.uri().path();
+
+    if let Some(times) = state.stalls.get_mut(path) {
+        if *times > 0 {
+            *times -= 1;
+            return None;
+        }
+    }
+
+    if let Some((failure, times)) = state.failures.get_mut(path) {
+        if *times > 0 {
+            *times -= 1;
+            return Some(status(*failure));
+        }
+    }
+
+    if request.headers().contains_key(RANGE) {
+        if let Some((failure, times)) = state.range_failures.get_mut(path) {
+            if *times > 0 {
+                *times -= 1;
+                return Some(status(*failure));
+            }
+        }
+
+        if let Some(times) = state.range_stalls.get_mut(path) {
+            if *times > 0 {
+                *times -= 1;
+                return Some(stalled_file(&mut state, &request));
+            }
+        }
+    }
+
+    let response = match (request.method(), path) {
+        (&Method::GET, _) if path.starts_with("/v2/client-version/") => {
+            client_version(&state, path)
+        }
+        (&Method::GET, "/v2/user-channel") => user_channel(&state, &request),
+        (&Method::POST, "/v2/logout") => logout(&request),
+        (&Method::POST, "/v1/authentication-ticket") => authentication_ticket(&request),
+        (&Method::GET | &Method::HEAD, _) => file(&state, &request),
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    Some(response)
+}
+
+/// `/v2/client-version/{binary type}/channel/{channel}`
+fn client_version(state: &State, path: &str) -> Response<Body> {
+    let parts: Vec<_> = path.trim_start_matches('/').split('/').collect();
+    let ["v2", "client-version", binary_type, "channel", channel] = parts[..] else {
+        return status(StatusCode::NOT_FOUND);
+    };
+
+    let Some((version, version_guid)) = state
+        .versions
+        .get(&(binary_type.to_owned(), channel.to_owned()))
+    else {
+        return status(StatusCode::NOT_FOUND);
+    };
+
+    json(serde_json::json!({
+        "version": version,
+        "clientVersionUpload": version_guid,
+        "bootstrapperVersion": "1, 6, 0, 5530470",
+    }))
+}
+
+fn user_channel(state: &State, request: &Request<Body>) -> Response<Body> {
+    if !authenticated(request) {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    let channel = state.user_channel.as_deref().unwrap_or("LIVE");
+    json(serde_json::json!({ "channelName": channel }))
+}
+
+/// Like Roblox, logging out without an X-CSRF token is rejected with a fresh token.
+fn logout(request: &Request<Body>) -> Response<Body> {
+    if !authenticated(request) {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header("x-csrf-token", CSRF_TOKEN)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn authentication_ticket(request: &Request<Body>) -> Response<Body> {
+    if !authenticated(request) {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+
+    if request.headers().get("x-csrf-token") != Some(&HeaderValue::from_static(CSRF_TOKEN)) {
+        return Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("x-csrf-token", CSRF_TOKEN)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    Response::builder()
+        .header("rbx-authentication-ticket", AUTHENTICATION_TICKET)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Serves a setup CDN file, honoring single `bytes=start-end` ranges like the real CDN.
+fn file(state: &State, request: &Request<Body>) -> Response<Body> {
+    let Some(contents) = state.files.get(request.uri().path()) else {
+        return status(StatusCode::NOT_FOUND);
+    };
+
+    let Some(range) = request.headers().get(RANGE) else {
+        return Response::builder()
+            .header(ACCEPT_RANGES, "bytes")
+            .body(Body::from(contents.to_owned()))
+            .unwrap();
+    };
+
+    let len = contents.len();
+    match parse_range(range, len) {
+        Some((start, end)) => Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(ACCEPT_RANGES, "bytes")
+            .header(CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+            .body(Body::from(contents[start..=end].to_owned()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{len}"))
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+/// Sends the first half of the requested range of a setup CDN file, then nothing more.
+fn stalled_file(state: &mut State, request: &Request<Body>) -> Response<Body> {
+    let Some(contents) = state.files.get(request.uri().path()) else {
+        return status(StatusCode::NOT_FOUND);
+    };
+
+    let len = contents.len();
+    let Some((start, end)) = request
+        .headers()
+        .get(RANGE)
+        .and_then(|range| parse_range(range, len))
+    else {
+        return status(StatusCode::RANGE_NOT_SATISFIABLE);
+    };
+
+    let range_len = end - start + 1;
+    let half = contents[start..start + range_len / 2].to_vec();
+    let (mut sender, body) = Body::channel();
+    let _ = sender.try_send_data(half.into());
+    state.stalled_bodies.push(sender);
+
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+        .header(CONTENT_LENGTH, range_len)
+        .body(body)
+        .unwrap()
+}
+
+/// Parses `bytes=start-end` or `bytes=start-` into inclusive bounds within `len`.
+fn parse_range(range: &HeaderValue, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.to_str().ok()?.strip_prefix("bytes=")?.split_once('-')?;
+
+    let start = start.parse::<usize>().ok()?;
+    let end = match end {
+        "" => len.checked_sub(1)?,
+        end => end.parse::<usize>().ok()?.min(len.checked_sub(1)?),
+    };
+
+    (start <= end).then_some((start, end))
+}
+
+/// Checks for our `.ROBLOSECURITY` cookie amongst the request's cookies.
+fn authenticated(request: &Request<Body>) -> bool {
+    let expected = format!(".ROBLOSECURITY={ROBLOSECURITY}");
+
+    request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .any(|cookie| cookie.trim() == expected)
+}
+
+fn json(value: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use deploy_history::{
+        client_version_info::ClientVersionInfo,
+        domain::{BinaryType, Channel},
+        get_deploy_logs_for_channel,
+        retry::RetryPolicy,
+    };
+    use reqwest::{
+        header::{CONTENT_LENGTH, RANGE},
+        Client, StatusCode,
+    };
+
+    use crate::MockRoblox;
+
+    #[tokio::test]
+    async fn serves_client_versions() {
+        let mock = MockRoblox::start();
+        mock.set_client_version(
+            "MacPlayer",
+            "live",
+            "0.553.0.5530470",
+            "version-d780cbcde4ab4f52",
+        );
+
+        let version_info = ClientVersionInfo::get(
+            &Client::new(),
+            &mock.endpoints(),
+            None,
+            &RetryPolicy::none(),
+            &Channel::Live,
+            &BinaryType::MacPlayer,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(version_info.to_string(), "0.553.0.5530470");
+        assert_eq!(version_info.version_guid, "version-d780cbcde4ab4f52");
+    }
+
+    #[tokio::test]
+    async fn serves_deploy_history() {
+        let mock = MockRoblox::start();
+        mock.add_file(
+            "/DeployHistory.txt",
+            "New WindowsPlayer version-4981d7ba0b92417b at 6/14/2021 3:26:16 PM, file version: 0, 483, 0, 424775, git hash: 6c7a4d5b ...\r\n",
+        );
+
+        let history = get_deploy_logs_for_channel(
+            &Client::new(),
+            &mock.endpoints(),
+            None,
+            &RetryPolicy::none(),
+            &Channel::Live,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(history.logs.len(), 1);
+        assert_eq!(history.logs[0].version_guid, "version-4981d7ba0b92417b");
+    }
+
+    #[tokio::test]
+    async fn serves_ranges() {
+        let mock = MockRoblox::start();
+        mock.add_file("/file.bin", "0123456789");
+        let url = format!("{}/file.bin", mock.url());
+        let client = Client::new();
+
+        let response = client.head(&url).send().await.unwrap();
+        assert_eq!(response.headers()[CONTENT_LENGTH], "10");
+
+        let response = client
+            .get(&url)
+            .header(RANGE, "bytes=2-5")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.text().await.unwrap(), "2345");
+
+        let response = client
+            .get(&url)
+            .header(RANGE, "bytes=20-30")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn fails_requests_on_demand() {
+        let mock = MockRoblox::start();
+        mock.add_file("/file.bin", "0123456789");
+        mock.fail_requests("/file.bin", StatusCode::SERVICE_UNAVAILABLE, 2);
+        let url = format!("{}/file.bin", mock.url());
+        let client = Client::new();
+
+        for _ in 0..2 {
+            let response = client.get(&url).send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "0123456789");
+    }
+}
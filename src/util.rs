@@ -30,3 +30,18 @@ pub fn get_root_directory() -> anyhow::Result<PathBuf> {
 
     Ok(current_dir)
 }
+
+/// Linux builds are shipped as a directory holding the executable beside the game's files.
+#[cfg(all(not(debug_assertions), target_os = "linux"))]
+pub fn get_root_directory() -> anyhow::Result<PathBuf> {
+    let current_exe = env::current_exe().context("Failed to get current executable")?;
+    let current_dir = current_exe
+        .parent()
+        .context(format!("Current executable has no parent: {current_exe:?}"))?;
+
+    let current_dir = current_dir.canonicalize().context(format!(
+        "Failed to canonicalize current executable: {current_dir:?}"
+    ))?;
+
+    Ok(current_dir)
+}